@@ -110,17 +110,11 @@ impl Cache {
         Ok(cache)
     }
 
-    pub fn write(&self) -> eyre::Result<()> {
-        if self.blocks.is_empty() {
-            return Err(eyre::Error::msg("cache can't be empty"));
-        }
-
-        // Ensure the cache directory exists
-        std::fs::create_dir_all(&self.dir)?;
-
+    /// Path of the cache file inside its directory.
+    pub fn path(&self) -> PathBuf {
         let file_name = get_block_cache_file_name(
             &self.network.clone(),
-            self.blocks[0].header.number,
+            self.blocks.first().map_or(0, |b| b.header.number),
             if self.blocks.len() == 1 {
                 None
             } else {
@@ -128,7 +122,18 @@ impl Cache {
             },
         );
 
-        let full_path = self.dir.join(file_name);
+        self.dir.join(file_name)
+    }
+
+    pub fn write(&self) -> eyre::Result<()> {
+        if self.blocks.is_empty() {
+            return Err(eyre::Error::msg("cache can't be empty"));
+        }
+
+        // Ensure the cache directory exists
+        std::fs::create_dir_all(&self.dir)?;
+
+        let full_path = self.path();
 
         debug!("Writing cache to {}", full_path.display());
 
@@ -144,17 +149,7 @@ impl Cache {
             return Err(eyre::Error::msg("tried to delete cache with no blocks"));
         }
 
-        let file_name = get_block_cache_file_name(
-            &self.network.clone(),
-            self.blocks[0].header.number,
-            if self.blocks.len() == 1 {
-                None
-            } else {
-                self.blocks.last().map(|b| b.header.number)
-            },
-        );
-
-        let full_path = self.dir.join(file_name);
+        let full_path = self.path();
         debug!("Deleting cache file {}", full_path.display());
 
         std::fs::remove_file(&full_path)?;
@@ -171,6 +166,11 @@ pub fn get_block_cache_file_name(network: &Network, from: u64, to: Option<u64>)
     }
 }
 
+/// Name of the file where the data fetched through `eth_getProof` for a block is checkpointed.
+pub fn get_checkpoint_file_name(network: &Network, block_number: u64) -> String {
+    format!("checkpoint_{network}_{block_number}.{CACHE_FILE_FORMAT}")
+}
+
 #[cfg(feature = "l2")]
 pub fn get_batch_cache_file_name(batch_number: u64) -> String {
     format!("cache_batch_{batch_number}.{CACHE_FILE_FORMAT}")
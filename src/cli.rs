@@ -6,13 +6,15 @@ use ethrex_guest_program::input::ProgramInput;
 use ethrex_l2_common::prover::ProofFormat;
 use ethrex_l2_rpc::signer::{LocalSigner, Signer};
 use ethrex_rlp::decode::RLPDecode;
-use ethrex_trie::{EMPTY_TRIE_HASH, InMemoryTrieDB, Node};
+use ethrex_trie::{EMPTY_TRIE_HASH, InMemoryTrieDB, Nibbles, Node};
 use eyre::{Context, OptionExt};
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
     collections::BTreeMap,
     fmt::Display,
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -23,21 +25,20 @@ use ethrex_blockchain::{
     fork_choice::apply_fork_choice,
     payload::{BuildPayloadArgs, PayloadBuildResult, create_payload},
 };
+use ethrex_common::U256;
+#[cfg(feature = "l2")]
+use ethrex_common::types::GenesisAccount;
 use ethrex_common::{
     Address, H256,
     types::{
-        AccountState, AccountUpdate, Block, Code, DEFAULT_BUILDER_GAS_CEIL, ELASTICITY_MULTIPLIER,
-        Receipt,
+        AccountState, AccountUpdate, Block, BlockBody, BlockHeader, ChainConfig, Code,
+        DEFAULT_BUILDER_GAS_CEIL, ELASTICITY_MULTIPLIER, Receipt,
         block_execution_witness::{GuestProgramState, RpcExecutionWitness},
     },
     utils::keccak,
 };
-#[cfg(feature = "l2")]
-use ethrex_common::{U256, types::GenesisAccount};
 use ethrex_prover::BackendType;
-#[cfg(not(feature = "l2"))]
-use ethrex_rpc::types::block_identifier::BlockIdentifier;
-use ethrex_rpc::{EthClient, debug::execution_witness::execution_witness_from_rpc_chain_config};
+use ethrex_rpc::debug::execution_witness::execution_witness_from_rpc_chain_config;
 use ethrex_storage::hash_address;
 use ethrex_storage::{EngineType, Store};
 #[cfg(feature = "l2")]
@@ -45,24 +46,63 @@ use ethrex_storage_rollup::EngineTypeRollup;
 use reqwest::Url;
 #[cfg(feature = "l2")]
 use std::collections::HashMap;
-#[cfg(feature = "l2")]
-use std::path::Path;
 #[cfg(not(feature = "l2"))]
 use tracing::debug;
-use tracing::info;
+use tracing::{info, warn};
 
 #[cfg(feature = "l2")]
 use crate::fetcher::get_batchdata;
 #[cfg(not(feature = "l2"))]
 use crate::plot_composition::analyze_and_display;
-use crate::{cache::Cache, fetcher::get_blockdata, report::Report, tx_builder::TxBuilder};
+#[cfg(not(feature = "l2"))]
+use crate::receipts_diff;
+use crate::rpc::{RpcClient, auth::RpcAuth, get_chain_id};
+#[cfg(not(feature = "l2"))]
+use crate::rpc::{
+    get_block, get_block_number, get_block_receipts, limit_request_rate, transport::NewHeads,
+};
+#[cfg(not(feature = "l2"))]
+use crate::supervisor::{load_worker_cache, write_worker_result};
 use crate::{
-    run::{exec, prove, run_tx},
+    cache::{Cache, get_block_cache_file_name},
+    fetcher::get_blockdata,
+    output_verification,
+    overrides::{Overrides, TxOverrides, read_state_override},
+    profiling::{RunStats, StatsExport, TxProfile, print_individual_runs, print_tx_profile},
+    proofs::SavedProof,
+    report::{Report, get_current_git_commit},
+    resource_usage::{UsagePhase, UsageRecorder},
+    tx_builder::TxBuilder,
+};
+#[cfg(not(feature = "l2"))]
+use crate::{
+    output_verification::GuestOutput,
+    run::{compiled_backends, exec_all_backends},
+};
+#[cfg(not(feature = "l2"))]
+use crate::{resource_usage::disable_process_usage, rpc::db::keep_ancestor_headers};
+use crate::{
+    run::{
+        Execution, Proving, TxReplay, TxSelection, exec_with_output, prove, run_txs,
+        run_txs_in_block, trace_tx_calls,
+    },
     slack::try_send_report_to_slack,
+    state_diff::{self, PostState, StateDiff},
+    supervisor::{BlockOutcome, run_in_worker},
+    timeout::{Phase, is_timeout, with_blocking_timeout, with_timeout},
 };
 use ethrex_config::networks::{
     HOLESKY_CHAIN_ID, HOODI_CHAIN_ID, MAINNET_CHAIN_ID, Network, PublicNetwork, SEPOLIA_CHAIN_ID,
 };
+#[cfg(not(feature = "l2"))]
+use std::collections::VecDeque;
+#[cfg(not(feature = "l2"))]
+use std::num::NonZeroUsize;
+#[cfg(not(feature = "l2"))]
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
 
 pub const VERSION_STRING: &str = env!("CARGO_PKG_VERSION");
 // 0x941e103320615d394a55708be13e45994c7d93b932b064dbcb2b511fe3254e2e is the
@@ -98,6 +138,18 @@ pub enum EthrexReplayCommand {
     #[cfg(not(feature = "l2"))]
     #[command(about = "Replay a single transaction")]
     Transaction(TransactionOpts),
+    #[cfg(not(feature = "l2"))]
+    #[command(
+        about = "Compare the receipts of a block with the canonical ones, transaction by transaction"
+    )]
+    Receipts(BlockOptions),
+    #[cfg(not(feature = "l2"))]
+    #[command(
+        about = "Execute a block with every backend compiled in and check that their outputs agree"
+    )]
+    CompareBackends(BlockOptions),
+    #[command(about = "Verify a proof saved when proving")]
+    Verify(VerifyOptions),
     #[cfg(feature = "l2")]
     #[command(subcommand, about = "L2 specific commands")]
     L2(L2Subcommand),
@@ -143,6 +195,28 @@ pub struct CommonOptions {
     pub action: Action,
     #[arg(long = "proof", value_enum, default_value_t = ProofType::default(), help_heading = "Replay Options")]
     pub proof_type: ProofType,
+    #[arg(
+        long,
+        help = "Directory to save proofs to",
+        value_parser,
+        default_value = "./replay_proofs",
+        help_heading = "Replay Options"
+    )]
+    pub proofs_dir: PathBuf,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up executing a block after this many seconds. Implies --isolate for `blocks`",
+        help_heading = "Replay Options"
+    )]
+    pub execute_timeout: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up proving a block after this many seconds. Implies --isolate for `blocks`",
+        help_heading = "Replay Options"
+    )]
+    pub prove_timeout: Option<u64>,
     #[arg(
         long,
         short,
@@ -151,6 +225,56 @@ pub struct CommonOptions {
         required = false
     )]
     pub verbose: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        help = "Format of the block report. `json` prints it to stdout instead of logging it",
+        help_heading = "Replay Options"
+    )]
+    pub report_format: OutputFormat,
+}
+
+#[derive(Parser, Clone, Default)]
+pub struct RpcAuthOptions {
+    #[arg(
+        long = "rpc-header",
+        value_name = "NAME:VALUE",
+        help = "Extra HTTP header to send with every RPC request. Can be repeated.",
+        help_heading = "RPC Options"
+    )]
+    pub rpc_headers: Vec<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File with a hex encoded JWT secret, used to sign a bearer token for every RPC request",
+        help_heading = "RPC Options",
+        conflicts_with = "rpc_bearer_token_file"
+    )]
+    pub rpc_jwt_secret: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File with a bearer token to send with every RPC request",
+        help_heading = "RPC Options"
+    )]
+    pub rpc_bearer_token_file: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Username for HTTP basic auth against the RPC endpoint",
+        help_heading = "RPC Options",
+        requires = "rpc_password_file",
+        conflicts_with_all = ["rpc_jwt_secret", "rpc_bearer_token_file"]
+    )]
+    pub rpc_user: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File with the password for HTTP basic auth against the RPC endpoint",
+        help_heading = "RPC Options",
+        requires = "rpc_user"
+    )]
+    pub rpc_password_file: Option<PathBuf>,
 }
 
 #[derive(Parser, Clone)]
@@ -160,6 +284,8 @@ pub struct EthrexReplayOptions {
     pub common: CommonOptions,
     #[arg(long, group = "data_source", help_heading = "Replay Options")]
     pub rpc_url: Option<Url>,
+    #[command(flatten)]
+    pub rpc_auth: RpcAuthOptions,
     #[arg(
         long,
         group = "data_source",
@@ -169,6 +295,14 @@ pub struct EthrexReplayOptions {
         conflicts_with = "cache_level"
     )]
     pub cached: bool,
+    #[arg(
+        long,
+        group = "data_source",
+        value_name = "PATH",
+        help = "Read blocks and execution witnesses from the datadir of a local ethrex node instead of RPC (requires the `rocksdb` feature)",
+        help_heading = "Replay Options"
+    )]
+    pub datadir: Option<PathBuf>,
     #[arg(
         long,
         help = "Network to use for replay (i.e. mainnet, sepolia, hoodi). If not specified will fetch from RPC",
@@ -188,10 +322,16 @@ pub struct EthrexReplayOptions {
         long,
         default_value = "on",
         help_heading = "Replay Options",
-        help = "Criteria to save a cache when fetching from RPC",
-        requires = "rpc_url"
+        help = "Criteria to save a cache when fetching from RPC or a datadir"
     )]
     pub cache_level: CacheLevel,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Give up fetching the data of a block after this many seconds",
+        help_heading = "Replay Options"
+    )]
+    pub fetch_timeout: Option<u64>,
     #[arg(long, env = "SLACK_WEBHOOK_URL", help_heading = "Replay Options")]
     pub slack_webhook_url: Option<Url>,
     #[arg(
@@ -204,11 +344,39 @@ pub struct EthrexReplayOptions {
     #[arg(
         long,
         default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
         help = "Number of times to repeat execution for profiling",
         help_heading = "Replay Options",
         requires = "no_zkvm"
     )]
     pub repeat: u32,
+    #[arg(
+        long,
+        help = "Decode the witness once and build the store of each `--repeat` run from it, reporting the first run apart from the ones that reused the decoded witness",
+        help_heading = "Replay Options",
+        requires = "no_zkvm"
+    )]
+    pub reuse_storage: bool,
+    #[arg(
+        long,
+        help = "Append the timings of the `--repeat` runs and their statistics to this file, as CSV if it ends in `.csv` or as a JSON array otherwise",
+        help_heading = "Replay Options",
+        requires = "no_zkvm"
+    )]
+    pub stats_out: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Also execute each transaction of the block individually with LEVM and rank them by execution time, gas used and Mgas/s",
+        help_heading = "Replay Options",
+        requires = "no_zkvm"
+    )]
+    pub tx_profile: bool,
+    #[arg(
+        long,
+        help = "If execution fails, re-execute the block with LEVM and report which accounts differ from the canonical post-state (fetched with eth_getProof, or from the cached witness of the next block)",
+        help_heading = "Replay Options"
+    )]
+    pub state_diff: bool,
     // CAUTION
     // This flag is used to create a benchmark file that is used by our CI for
     // updating benchmarks from https://docs.ethrex.xyz/benchmarks/.
@@ -294,7 +462,7 @@ impl Display for Action {
     }
 }
 
-#[derive(Clone, Debug, ValueEnum, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, ValueEnum, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum ProofType {
     #[default]
     Compressed,
@@ -328,6 +496,21 @@ pub enum CacheLevel {
     On,
 }
 
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum CallTraceFormat {
+    /// JSON, as returned by geth's `debug_traceTransaction`.
+    Json,
+    /// Indented tree, for reading in the terminal.
+    Pretty,
+}
+
 #[derive(ValueEnum, Clone, Debug, PartialEq, Eq, Default)]
 pub enum NotificationLevel {
     #[default]
@@ -380,24 +563,197 @@ pub struct BlocksOptions {
         conflicts_with = "blocks"
     )]
     pub only_eth_proofs_blocks: bool,
+    #[arg(
+        long,
+        help = "Execute or prove each block in its own worker process, so a crash is reported as a failed block and the loop moves on.",
+        help_heading = "Replay Options"
+    )]
+    pub isolate: bool,
+    #[arg(
+        long,
+        default_value = "1",
+        help = "Number of blocks to execute concurrently. Blocks are still reported in order.",
+        help_heading = "Replay Options",
+        conflicts_with_all = ["endless", "no_zkvm"]
+    )]
+    pub jobs: NonZeroUsize,
+    #[arg(
+        long,
+        value_name = "REQUESTS_PER_SECOND",
+        help = "Maximum number of RPC requests per second, shared by all the blocks being fetched.",
+        help_heading = "RPC Options"
+    )]
+    pub rpc_rate_limit: Option<u32>,
+    #[arg(long, hide = true, requires = "worker_result")]
+    worker_cache: Option<PathBuf>,
+    #[arg(long, hide = true, requires = "worker_cache")]
+    worker_result: Option<PathBuf>,
     #[command(flatten)]
     opts: EthrexReplayOptions,
 }
 
 #[derive(Parser)]
+pub struct VerifyOptions {
+    #[arg(
+        help = "Proof file, saved under `--proofs-dir` when proving",
+        help_heading = "Command Options"
+    )]
+    pub proof: PathBuf,
+}
+
+#[derive(Parser)]
+#[command(group(ArgGroup::new("tx_selection").required(true).args(["tx_hash", "tx_index"])))]
 pub struct TransactionOpts {
     #[arg(help = "Transaction hash.", help_heading = "Command Options")]
-    tx_hash: H256,
+    tx_hash: Option<H256>,
+    #[arg(
+        long,
+        value_name = "INDEX|RANGE",
+        value_parser = parse_tx_index,
+        requires = "block_number",
+        conflicts_with = "call_trace",
+        help = "Index of the transaction inside the block, or a range of indices (e.g. `5..20`, end exclusive, or `5..=20`)",
+        help_heading = "Command Options"
+    )]
+    pub tx_index: Option<Range<usize>>,
     #[arg(
         long,
         help = "Block number containing the transaction. Necessary in cached mode.",
         help_heading = "Command Options"
     )]
     pub block_number: Option<u64>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Print the geth-compatible `callTracer` tree of the transaction instead of its receipt and state transitions",
+        help_heading = "Command Options"
+    )]
+    pub call_trace: Option<CallTraceFormat>,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        conflicts_with = "call_trace",
+        help = "Format of the receipt and state transitions",
+        help_heading = "Command Options"
+    )]
+    pub output: OutputFormat,
+    #[command(flatten)]
+    pub overrides: TxOverrideOptions,
     #[command(flatten)]
     opts: EthrexReplayOptions,
 }
 
+impl TransactionOpts {
+    fn prints_json(&self) -> bool {
+        self.output == OutputFormat::Json || self.call_trace == Some(CallTraceFormat::Json)
+    }
+}
+
+/// Changes to the replayed transaction and the state it runs on, to try out what would have
+/// happened if it had been sent differently.
+#[derive(Parser, Clone, Default)]
+pub struct TxOverrideOptions {
+    #[arg(
+        long,
+        help = "Replace the gas limit",
+        help_heading = "Override Options"
+    )]
+    pub override_gas_limit: Option<u64>,
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = parse_hex_bytes,
+        help = "Replace the calldata",
+        help_heading = "Override Options"
+    )]
+    pub override_data: Option<Bytes>,
+    #[arg(
+        long,
+        value_parser = parse_u256,
+        help = "Replace the value in wei (decimal or 0x-prefixed hex)",
+        help_heading = "Override Options"
+    )]
+    pub override_value: Option<U256>,
+    #[arg(
+        long,
+        help = "Replace the sender, no signature is needed",
+        help_heading = "Override Options"
+    )]
+    pub override_sender: Option<Address>,
+    #[arg(
+        long,
+        help = "Replace the max fee per gas, or the gas price of legacy transactions",
+        help_heading = "Override Options"
+    )]
+    pub override_max_fee_per_gas: Option<u64>,
+    #[arg(
+        long,
+        help = "Replace the max priority fee per gas",
+        help_heading = "Override Options"
+    )]
+    pub override_max_priority_fee_per_gas: Option<u64>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "JSON file with balances, nonces, code and storage to replace before the transaction, in the format of eth_call's stateOverride",
+        help_heading = "Override Options"
+    )]
+    pub state_override: Option<PathBuf>,
+}
+
+impl TxOverrideOptions {
+    fn to_overrides(&self) -> eyre::Result<Overrides> {
+        Ok(Overrides {
+            tx: TxOverrides {
+                gas_limit: self.override_gas_limit,
+                data: self.override_data.clone(),
+                value: self.override_value,
+                sender: self.override_sender,
+                max_fee_per_gas: self.override_max_fee_per_gas,
+                max_priority_fee_per_gas: self.override_max_priority_fee_per_gas,
+            },
+            state: self
+                .state_override
+                .as_deref()
+                .map(read_state_override)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+}
+
+fn parse_tx_index(s: &str) -> Result<Range<usize>, String> {
+    let parse = |index: &str| index.trim().parse::<usize>().map_err(|e| e.to_string());
+    let after = |index: usize| {
+        index
+            .checked_add(1)
+            .ok_or_else(|| format!("transaction index {index} is too large"))
+    };
+
+    if let Some((start, end)) = s.split_once("..=") {
+        Ok(parse(start)?..after(parse(end)?)?)
+    } else if let Some((start, end)) = s.split_once("..") {
+        Ok(parse(start)?..parse(end)?)
+    } else {
+        let index = parse(s)?;
+        Ok(index..after(index)?)
+    }
+}
+
+fn parse_hex_bytes(s: &str) -> Result<Bytes, String> {
+    hex::decode(s.trim_start_matches("0x"))
+        .map(Bytes::from)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_u256(s: &str) -> Result<U256, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+        None => U256::from_dec_str(s).map_err(|e| e.to_string()),
+    }
+}
+
 #[cfg(feature = "l2")]
 #[derive(Parser)]
 pub struct BatchOptions {
@@ -493,6 +849,8 @@ pub struct GenerateInputOptions {
         help_heading = "Replay Options"
     )]
     rpc_url: Url,
+    #[command(flatten)]
+    rpc_auth: RpcAuthOptions,
 }
 
 #[cfg(not(feature = "l2"))]
@@ -525,6 +883,8 @@ pub struct BlockCompositionOptions {
     pub to: Option<u64>,
     #[arg(long, group = "data_source", help_heading = "Data Source")]
     pub rpc_url: Option<Url>,
+    #[command(flatten)]
+    pub rpc_auth: RpcAuthOptions,
     #[arg(
         long,
         group = "data_source",
@@ -557,10 +917,42 @@ pub struct BlockCompositionOptions {
 }
 
 impl EthrexReplayCommand {
+    /// Whether the command prints a JSON document to stdout, in which case logs have to go
+    /// to stderr so that they don't end up mixed with it.
+    pub fn prints_json(&self) -> bool {
+        let report_json = |common: &CommonOptions| common.report_format == OutputFormat::Json;
+        match self {
+            #[cfg(not(feature = "l2"))]
+            Self::Block(BlockOptions { opts, .. }) => report_json(&opts.common),
+            #[cfg(not(feature = "l2"))]
+            Self::Blocks(BlocksOptions { opts, .. }) => report_json(&opts.common),
+            #[cfg(not(feature = "l2"))]
+            Self::Custom(CustomSubcommand::Block(block_opts))
+            | Self::Custom(CustomSubcommand::Batch(CustomBatchOptions { block_opts, .. })) => {
+                report_json(&block_opts.common)
+            }
+            #[cfg(not(feature = "l2"))]
+            Self::Transaction(tx_opts) => tx_opts.prints_json(),
+            #[cfg(feature = "l2")]
+            Self::L2(L2Subcommand::Transaction(tx_opts)) => tx_opts.prints_json(),
+            #[cfg(feature = "l2")]
+            Self::L2(
+                L2Subcommand::Block(BlockOptions { opts, .. })
+                | L2Subcommand::Batch(BatchOptions { opts, .. }),
+            ) => report_json(&opts.common),
+            #[cfg(feature = "l2")]
+            Self::L2(L2Subcommand::Custom(
+                CustomSubcommand::Block(block_opts)
+                | CustomSubcommand::Batch(CustomBatchOptions { block_opts, .. }),
+            )) => report_json(&block_opts.common),
+            _ => false,
+        }
+    }
+
     pub async fn run(self) -> eyre::Result<()> {
         match self {
             #[cfg(not(feature = "l2"))]
-            Self::Block(block_opts) => replay_block(block_opts.clone()).await?,
+            Self::Block(block_opts) => replay_block(block_opts.clone(), false).await?,
             #[cfg(not(feature = "l2"))]
             Self::Blocks(BlocksOptions {
                 mut blocks,
@@ -568,8 +960,33 @@ impl EthrexReplayCommand {
                 to,
                 endless,
                 only_eth_proofs_blocks,
+                isolate,
+                jobs,
+                rpc_rate_limit,
+                worker_cache,
+                worker_result,
                 opts,
             }) => {
+                // Case this process is a worker spawned by an `--isolate` supervisor.
+                if let (Some(cache_path), Some(result_path)) = (worker_cache, worker_result) {
+                    let cache = load_worker_cache(&cache_path)?;
+                    let outcome = run_block(&opts, cache).await?;
+                    write_worker_result(&result_path, outcome)?;
+                    // Don't wait for a timed out execution or proving still running on a
+                    // blocking thread, the supervisor already has its result.
+                    std::process::exit(0);
+                }
+
+                if let Some(requests_per_second) = rpc_rate_limit {
+                    limit_request_rate(requests_per_second);
+                }
+
+                // Timed out executions and proofs can't be interrupted, so they run in
+                // workers that are killed once their time is up.
+                let isolate = isolate
+                    || opts.common.execute_timeout.is_some()
+                    || opts.common.prove_timeout.is_some();
+
                 // Necessary checks for running cached blocks only.
                 if opts.cached && blocks.is_empty() {
                     if from.is_none() && to.is_none() {
@@ -591,6 +1008,11 @@ impl EthrexReplayCommand {
                 if !blocks.is_empty() {
                     blocks.sort();
 
+                    if jobs.get() > 1 {
+                        return replay_blocks_concurrently(blocks, &opts, jobs.get(), isolate)
+                            .await;
+                    }
+
                     for block in blocks.clone() {
                         info!(
                             "{} block: {block}",
@@ -601,14 +1023,13 @@ impl EthrexReplayCommand {
                             }
                         );
 
-                        Box::pin(async {
-                            Self::Block(BlockOptions {
+                        Box::pin(replay_block(
+                            BlockOptions {
                                 block: Some(block),
                                 opts: opts.clone(),
-                            })
-                            .run()
-                            .await
-                        })
+                            },
+                            isolate,
+                        ))
                         .await?;
                     }
 
@@ -616,7 +1037,19 @@ impl EthrexReplayCommand {
                 }
 
                 // It will only be used in case from or to weren't specified or in endless mode. We can unwrap as cached mode won't reach those places.
-                let maybe_rpc = opts.rpc_url.as_ref();
+                let maybe_rpc = opts
+                    .rpc_url
+                    .clone()
+                    .map(|url| -> eyre::Result<_> {
+                        Ok(RpcClient::new(url, RpcAuth::new(&opts.rpc_auth)?))
+                    })
+                    .transpose()?;
+
+                if maybe_rpc.is_none() && (from.is_none() || to.is_none()) {
+                    return Err(eyre::Error::msg(
+                        "--rpc-url is required to replay up to the latest block",
+                    ));
+                }
 
                 let from = match from {
                     // Case --from is set
@@ -628,7 +1061,7 @@ impl EthrexReplayCommand {
                     // * If we reach this point, --endless must be set (constraint by clap)
                     None => {
                         fetch_latest_block_number(
-                            maybe_rpc.unwrap().clone(),
+                            maybe_rpc.as_ref().unwrap(),
                             only_eth_proofs_blocks,
                         )
                         .await?
@@ -643,7 +1076,7 @@ impl EthrexReplayCommand {
                     // * If we reach this point, --from or --endless must be set (constraint by clap)
                     None => {
                         fetch_latest_block_number(
-                            maybe_rpc.unwrap().clone(),
+                            maybe_rpc.as_ref().unwrap(),
                             only_eth_proofs_blocks,
                         )
                         .await?
@@ -656,9 +1089,22 @@ impl EthrexReplayCommand {
                     ));
                 }
 
+                if jobs.get() > 1 {
+                    let blocks = (from..=to)
+                        .filter(|block| !only_eth_proofs_blocks || block % 100 == 0)
+                        .collect();
+                    return replay_blocks_concurrently(blocks, &opts, jobs.get(), isolate).await;
+                }
+
                 let mut block_to_replay = from;
                 let mut last_block_to_replay = to;
 
+                // Over WebSocket and IPC, new blocks are pushed by the node instead of polled.
+                let mut new_heads = match &maybe_rpc {
+                    Some(client) if endless => client.subscribe_new_heads().await?,
+                    _ => None,
+                };
+
                 while block_to_replay <= last_block_to_replay {
                     if only_eth_proofs_blocks && block_to_replay % 100 != 0 {
                         block_to_replay += 1;
@@ -666,26 +1112,24 @@ impl EthrexReplayCommand {
                         // Case --endless is set, we want to update the `to` so
                         // we can keep checking for new blocks
                         if endless && block_to_replay > last_block_to_replay {
-                            last_block_to_replay = fetch_latest_block_number(
-                                maybe_rpc.unwrap().clone(),
+                            last_block_to_replay = wait_for_latest_block_number(
+                                maybe_rpc.as_ref().unwrap(),
+                                &mut new_heads,
                                 only_eth_proofs_blocks,
                             )
                             .await?;
-
-                            tokio::time::sleep(Duration::from_secs(1)).await;
                         }
 
                         continue;
                     }
 
-                    Box::pin(async {
-                        Self::Block(BlockOptions {
+                    Box::pin(replay_block(
+                        BlockOptions {
                             block: Some(block_to_replay),
                             opts: opts.clone(),
-                        })
-                        .run()
-                        .await
-                    })
+                        },
+                        isolate,
+                    ))
                     .await?;
 
                     block_to_replay += 1;
@@ -693,13 +1137,12 @@ impl EthrexReplayCommand {
                     // Case --endless is set, we want to update the `to` so
                     // we can keep checking for new blocks
                     while endless && block_to_replay > last_block_to_replay {
-                        last_block_to_replay = fetch_latest_block_number(
-                            maybe_rpc.unwrap().clone(),
+                        last_block_to_replay = wait_for_latest_block_number(
+                            maybe_rpc.as_ref().unwrap(),
+                            &mut new_heads,
                             only_eth_proofs_blocks,
                         )
                         .await?;
-
-                        tokio::time::sleep(Duration::from_secs(1)).await;
                     }
                 }
             }
@@ -722,10 +1165,17 @@ impl EthrexReplayCommand {
             })) => {
                 let opts = EthrexReplayOptions {
                     rpc_url: Some(Url::parse("http://localhost:8545")?),
+                    rpc_auth: RpcAuthOptions::default(),
                     cached: false,
+                    datadir: None,
                     no_zkvm: false,
                     repeat: 1,
+                    reuse_storage: false,
+                    stats_out: None,
+                    state_diff: false,
+                    tx_profile: false,
                     cache_level: CacheLevel::default(),
+                    fetch_timeout: None,
                     common: block_opts.common.clone(),
                     slack_webhook_url: None,
                     bench: false,
@@ -739,6 +1189,11 @@ impl EthrexReplayCommand {
             #[cfg(not(feature = "l2"))]
             Self::Transaction(opts) => replay_transaction(opts).await?,
             #[cfg(not(feature = "l2"))]
+            Self::Receipts(block_opts) => replay_receipts(block_opts).await?,
+            #[cfg(not(feature = "l2"))]
+            Self::CompareBackends(block_opts) => compare_backends(block_opts).await?,
+            Self::Verify(VerifyOptions { proof }) => verify_proof(&proof)?,
+            #[cfg(not(feature = "l2"))]
             Self::BlockComposition(opts) => {
                 let (start, end) = match (opts.block, opts.from) {
                     (Some(block), _) => (block, block),
@@ -767,8 +1222,8 @@ impl EthrexReplayCommand {
                     }
                     blocks
                 } else {
-                    let rpc_url = opts.rpc_url.as_ref().unwrap(); // enforced by clap
-                    let eth_client = EthClient::new(rpc_url.clone())?;
+                    let rpc_url = opts.rpc_url.clone().unwrap(); // enforced by clap
+                    let client = RpcClient::new(rpc_url, RpcAuth::new(&opts.rpc_auth)?);
                     info!(
                         "Fetching blocks from RPC: {start} to {end} ({} blocks)",
                         end - start + 1
@@ -776,9 +1231,7 @@ impl EthrexReplayCommand {
                     let mut blocks = vec![];
                     for block_number in start..=end {
                         debug!("Fetching block {block_number}");
-                        let rpc_block = eth_client
-                            .get_block_by_number(BlockIdentifier::Number(block_number), true)
-                            .await?;
+                        let rpc_block = get_block(&client, block_number as usize, true).await?;
 
                         let block = rpc_block.try_into().map_err(|e| {
                             eyre::eyre!("Failed to convert rpc block to block: {}", e)
@@ -799,17 +1252,26 @@ impl EthrexReplayCommand {
                 to,
                 output_dir,
                 rpc_url,
+                rpc_auth,
             }) => {
+                let client = RpcClient::new(rpc_url.clone(), RpcAuth::new(&rpc_auth)?);
                 let opts = EthrexReplayOptions {
                     common: CommonOptions::default(),
                     rpc_url: Some(rpc_url.clone()),
+                    rpc_auth,
                     cached: false,
+                    datadir: None,
                     network: None,
                     cache_dir: PathBuf::from("./replay_cache"),
                     cache_level: CacheLevel::Off,
+                    fetch_timeout: None,
                     slack_webhook_url: None,
                     no_zkvm: false,
                     repeat: 1,
+                    reuse_storage: false,
+                    stats_out: None,
+                    state_diff: false,
+                    tx_profile: false,
                     bench: false,
                     notification_level: NotificationLevel::Off,
                 };
@@ -828,7 +1290,7 @@ impl EthrexReplayCommand {
                     })?;
                     let to = match to {
                         Some(to) => to,
-                        None => fetch_latest_block_number(rpc_url.clone(), false).await?,
+                        None => fetch_latest_block_number(&client, false).await?,
                     };
                     (from..=to).collect()
                 };
@@ -861,57 +1323,39 @@ impl EthrexReplayCommand {
                 }
             }
             #[cfg(feature = "l2")]
-            Self::L2(L2Subcommand::Transaction(TransactionOpts {
-                tx_hash,
-                opts,
-                block_number,
-            })) => {
-                replay_transaction(TransactionOpts {
-                    tx_hash,
-                    opts,
-                    block_number,
-                })
-                .await?
-            }
+            Self::L2(L2Subcommand::Transaction(tx_opts)) => replay_transaction(tx_opts).await?,
             #[cfg(feature = "l2")]
             Self::L2(L2Subcommand::Batch(BatchOptions { batch, opts })) => {
                 if opts.cached {
                     unimplemented!("cached mode is not implemented yet");
                 }
 
-                let (eth_client, network) = setup_rpc(&opts).await?;
+                let (client, network) = setup_rpc(&opts).await?;
 
-                let cache = get_batchdata(eth_client, network, batch, opts.cache_dir).await?;
+                let cache = get_batchdata(client, network, batch, opts.cache_dir.clone()).await?;
 
-                let backend = backend(&opts.common.zkvm)?;
+                if opts.no_zkvm {
+                    let execution_result = replay_no_zkvm(cache, &opts).await;
 
-                match opts.common.action {
-                    Action::Execute => {
-                        let execution_result = exec(backend, cache.clone()).await;
+                    println!("Batch {batch} execution result: {execution_result:?}");
 
-                        println!("Batch {batch} execution result: {execution_result:?}");
-                    }
-                    Action::Prove => {
-                        // Always execute before proving, unless it's ZisK.
-                        // This is because of ZisK's client initializing MPI, which can't be done
-                        // more than once in the same process.
-                        // https://docs.open-mpi.org/en/v5.0.1/man-openmpi/man3/MPI_Init_thread.3.html#description
-                        #[cfg(not(feature = "zisk"))]
-                        {
-                            let execution_result = exec(backend, cache.clone()).await;
-
-                            println!("Batch {batch} execution result: {execution_result:?}");
-                        }
+                    return Ok(());
+                }
 
-                        let proving_result =
-                            prove(backend, opts.common.proof_type, cache.clone()).await;
+                let backend = backend(&opts.common.zkvm)?;
+                let usage = UsageRecorder::new(matches!(opts.common.resource, Resource::GPU));
 
-                        println!("Batch {batch} proving result: {proving_result:?}");
-                    }
+                let outcome = exec_and_prove(backend, &cache, &opts.common, &usage).await;
+
+                if let Some(execution_result) = outcome.execution_result {
+                    println!("Batch {batch} execution result: {execution_result:?}");
+                }
+                if let Some(proving_result) = outcome.proving_result {
+                    println!("Batch {batch} proving result: {proving_result:?}");
                 }
             }
             #[cfg(feature = "l2")]
-            Self::L2(L2Subcommand::Block(block_opts)) => replay_block(block_opts).await?,
+            Self::L2(L2Subcommand::Block(block_opts)) => replay_block(block_opts, false).await?,
             #[cfg(feature = "l2")]
             Self::L2(L2Subcommand::Custom(CustomSubcommand::Block(block_opts))) => {
                 Box::pin(async move {
@@ -934,10 +1378,17 @@ impl EthrexReplayCommand {
                 let opts = EthrexReplayOptions {
                     common: block_opts.common.clone(),
                     rpc_url: Some(Url::parse("http://localhost:8545")?),
+                    rpc_auth: RpcAuthOptions::default(),
                     cached: false,
+                    datadir: None,
                     no_zkvm: false,
                     repeat: 1,
+                    reuse_storage: false,
+                    stats_out: None,
+                    state_diff: false,
+                    tx_profile: false,
                     cache_level: CacheLevel::default(),
+                    fetch_timeout: None,
                     slack_webhook_url: None,
                     bench: false,
                     cache_dir: PathBuf::from("./replay_cache"),
@@ -953,11 +1404,14 @@ impl EthrexReplayCommand {
     }
 }
 
-pub async fn setup_rpc(opts: &EthrexReplayOptions) -> eyre::Result<(EthClient, Network)> {
-    let eth_client = EthClient::new(opts.rpc_url.as_ref().unwrap().clone())?;
-    let chain_id = eth_client.get_chain_id().await?.as_u64();
+pub async fn setup_rpc(opts: &EthrexReplayOptions) -> eyre::Result<(RpcClient, Network)> {
+    let client = RpcClient::new(
+        opts.rpc_url.as_ref().unwrap().clone(),
+        RpcAuth::new(&opts.rpc_auth)?,
+    );
+    let chain_id = get_chain_id(&client).await?;
     let network = network_from_chain_id(chain_id);
-    Ok((eth_client, network))
+    Ok((client, network))
 }
 
 fn write_program_input(output_path: &PathBuf, program_input: &ProgramInput) -> eyre::Result<()> {
@@ -991,109 +1445,159 @@ fn write_program_input(output_path: &PathBuf, program_input: &ProgramInput) -> e
     Ok(())
 }
 
-async fn prepare_no_zkvm_state(cache: &Cache) -> eyre::Result<(Store, Block, String)> {
-    if cache.blocks.len() > 1 {
-        eyre::bail!("Cache for L1 witness should contain only one block.");
-    }
-
-    info!("Preparing Storage for execution without zkVM");
+/// State the witness of a cache covers, decoded into the nodes, codes and headers a store is
+/// built from. With `--reuse-storage`, it's decoded once and a fresh store is built from it
+/// before each `--repeat` run. Only decoding is saved: every run still writes its state into a
+/// new, cold store.
+struct DecodedWitness {
+    chain_config: ChainConfig,
+    state_trie_nodes: Vec<(Nibbles, Vec<u8>)>,
+    storage_trie_nodes: Vec<(H256, Vec<(Nibbles, Vec<u8>)>)>,
+    codes: Vec<Code>,
+    block_headers: Vec<BlockHeader>,
+}
 
-    let chain_config = cache.get_chain_config()?;
-    let block = cache.blocks[0].clone();
+impl DecodedWitness {
+    fn decode(cache: &Cache) -> eyre::Result<Self> {
+        info!("Preparing Storage for execution without zkVM");
 
-    let witness = execution_witness_from_rpc_chain_config(
-        cache.witness.clone(),
-        chain_config,
-        cache.get_first_block_number()?,
-    )?;
+        let chain_config = cache.get_chain_config()?;
 
-    let guest_program = GuestProgramState::try_from(witness.clone())?;
+        let witness = execution_witness_from_rpc_chain_config(
+            cache.witness.clone(),
+            chain_config,
+            cache.get_first_block_number()?,
+        )?;
 
-    // This will contain all code hashes with the corresponding bytecode
-    // For the code hashes that we don't have we'll fill it with <CodeHash, Bytes::new()>
-    let mut all_codes_hashed = guest_program.codes_hashed.clone();
+        let guest_program = GuestProgramState::try_from(witness.clone())?;
 
-    let mut store = Store::new("nothing", EngineType::InMemory)?;
+        // This will contain all code hashes with the corresponding bytecode
+        // For the code hashes that we don't have we'll fill it with <CodeHash, Bytes::new()>
+        let mut all_codes_hashed = guest_program.codes_hashed.clone();
 
-    // - Set up state trie nodes
-    let state_root = guest_program.parent_block_header.state_root;
+        // - Set up state trie nodes
+        let state_root = guest_program.parent_block_header.state_root;
 
-    let all_nodes: BTreeMap<H256, Node> = cache
-        .witness
-        .state
-        .iter()
-        .filter_map(|b| {
-            if b.as_ref() == [0x80] {
-                return None;
-            } // skip nulls
-            let h = keccak(b);
-            Some(Node::decode(b).map(|node| (h, node)))
-        })
-        .collect::<Result<_, _>>()?;
+        let all_nodes: BTreeMap<H256, Node> = cache
+            .witness
+            .state
+            .iter()
+            .filter_map(|b| {
+                if b.as_ref() == [0x80] {
+                    return None;
+                } // skip nulls
+                let h = keccak(b);
+                Some(Node::decode(b).map(|node| (h, node)))
+            })
+            .collect::<Result<_, _>>()?;
 
-    let state_trie = InMemoryTrieDB::from_nodes(state_root, &all_nodes)?;
+        let state_trie = InMemoryTrieDB::from_nodes(state_root, &all_nodes)?;
 
-    let state_trie_nodes = get_trie_nodes_with_dummies(state_trie);
+        let state_trie_nodes = get_trie_nodes_with_dummies(state_trie);
 
-    let trie = store.open_direct_state_trie(*EMPTY_TRIE_HASH)?;
+        // - Set up all storage tries for all addresses in the execution witness
+        let addresses: Vec<Address> = witness
+            .keys
+            .iter()
+            .filter(|k| k.len() == Address::len_bytes())
+            .map(|k| Address::from_slice(k))
+            .collect();
 
-    trie.db().put_batch(state_trie_nodes)?;
+        let mut storage_trie_nodes = Vec::new();
 
-    // - Set up all storage tries for all addresses in the execution witness
-    let addresses: Vec<Address> = witness
-        .keys
-        .iter()
-        .filter(|k| k.len() == Address::len_bytes())
-        .map(|k| Address::from_slice(k))
-        .collect();
+        for address in &addresses {
+            let hashed_address = hash_address(address);
 
-    for address in &addresses {
-        let hashed_address = hash_address(address);
+            // Account state may not be in the state trie
+            let Some(account_state_rlp) = guest_program.state_trie.get(&hashed_address)? else {
+                continue;
+            };
 
-        // Account state may not be in the state trie
-        let Some(account_state_rlp) = guest_program.state_trie.get(&hashed_address)? else {
-            continue;
-        };
+            let account_state = AccountState::decode(&account_state_rlp)?;
 
-        let account_state = AccountState::decode(&account_state_rlp)?;
+            // If code hash of account isn't present insert empty code so that if not found the execution doesn't break.
+            let code_hash = account_state.code_hash;
+            all_codes_hashed.entry(code_hash).or_insert(Code::default());
 
-        // If code hash of account isn't present insert empty code so that if not found the execution doesn't break.
-        let code_hash = account_state.code_hash;
-        all_codes_hashed.entry(code_hash).or_insert(Code::default());
+            let storage_root = account_state.storage_root;
+            let Ok(storage_trie) = InMemoryTrieDB::from_nodes(storage_root, &all_nodes) else {
+                continue;
+            };
 
-        let storage_root = account_state.storage_root;
-        let Ok(storage_trie) = InMemoryTrieDB::from_nodes(storage_root, &all_nodes) else {
-            continue;
-        };
+            let nodes = get_trie_nodes_with_dummies(storage_trie);
 
-        let storage_trie_nodes = get_trie_nodes_with_dummies(storage_trie);
+            // If there isn't any storage trie node we don't need to write anything
+            if nodes.is_empty() {
+                continue;
+            }
 
-        // If there isn't any storage trie node we don't need to write anything
-        if storage_trie_nodes.is_empty() {
-            continue;
+            storage_trie_nodes.push((H256::from_slice(&hashed_address), nodes));
         }
 
-        let storage_trie_nodes = vec![(H256::from_slice(&hashed_address), storage_trie_nodes)];
+        let codes = all_codes_hashed
+            .into_iter()
+            .map(|(code_hash, mut code)| {
+                code.hash = code_hash;
+                code
+            })
+            .collect();
+
+        Ok(Self {
+            chain_config,
+            state_trie_nodes,
+            storage_trie_nodes,
+            codes,
+            block_headers: guest_program.block_headers.into_values().collect(),
+        })
+    }
+
+    /// Builds a fresh in-memory store holding the decoded state, on top of which the blocks of
+    /// the cache can be executed in order.
+    async fn build_store(&self) -> eyre::Result<Store> {
+        let mut store = Store::new("nothing", EngineType::InMemory)?;
+
+        let trie = store.open_direct_state_trie(*EMPTY_TRIE_HASH)?;
+
+        trie.db().put_batch(self.state_trie_nodes.clone())?;
 
         store
-            .write_storage_trie_nodes_batch(storage_trie_nodes)
+            .write_storage_trie_nodes_batch(self.storage_trie_nodes.clone())
             .await?;
-    }
 
-    store.set_chain_config(&chain_config).await?;
+        store.set_chain_config(&self.chain_config).await?;
 
-    // Add codes to DB
-    for (code_hash, mut code) in all_codes_hashed {
-        code.hash = code_hash;
-        store.add_account_code(code).await?;
-    }
+        // Add codes to DB
+        for code in &self.codes {
+            store.add_account_code(code.clone()).await?;
+        }
+
+        // Add block headers to DB
+        for header in &self.block_headers {
+            store
+                .add_block_header(header.hash(), header.clone())
+                .await?;
+        }
 
-    // Add block headers to DB
-    for (_n, header) in guest_program.block_headers.clone() {
-        store.add_block_header(header.hash(), header).await?;
+        Ok(store)
     }
+}
+
+/// Blockchain executing the blocks of a `--no-zkvm` run, configured for L2 blocks when built
+/// with the `l2` feature.
+fn no_zkvm_blockchain(store: Store) -> Blockchain {
+    #[cfg(feature = "l2")]
+    let r#type = ethrex_blockchain::BlockchainType::L2(ethrex_blockchain::L2Config::default());
+    #[cfg(not(feature = "l2"))]
+    let r#type = ethrex_blockchain::BlockchainType::L1;
 
-    Ok((store, block, cache.network.to_string()))
+    Blockchain::new(
+        store,
+        BlockchainOptions {
+            perf_logs_enabled: true,
+            r#type,
+            ..BlockchainOptions::default()
+        },
+    )
 }
 
 async fn replay_no_zkvm(cache: Cache, opts: &EthrexReplayOptions) -> eyre::Result<Duration> {
@@ -1108,6 +1612,10 @@ async fn replay_no_zkvm(cache: Cache, opts: &EthrexReplayOptions) -> eyre::Resul
     let repeat = opts.repeat;
     let mut prep_durations = Vec::with_capacity(repeat as usize);
     let mut exec_durations = Vec::with_capacity(repeat as usize);
+    // Execution times of each block of the cache, across runs.
+    let mut block_durations = vec![Vec::with_capacity(repeat as usize); cache.blocks.len()];
+    let network = cache.network.to_string();
+    let mut decoded_witness = None;
 
     for i in 0..repeat {
         if repeat > 1 {
@@ -1115,23 +1623,33 @@ async fn replay_no_zkvm(cache: Cache, opts: &EthrexReplayOptions) -> eyre::Resul
         }
 
         let prep_start = Instant::now();
-        let (store, block, network) = prepare_no_zkvm_state(&cache).await?;
-        let blockchain = Blockchain::new(
-            store,
-            BlockchainOptions {
-                perf_logs_enabled: true,
-                ..BlockchainOptions::default()
-            },
-        );
+        let store = match &decoded_witness {
+            Some(decoded_witness) => decoded_witness.build_store().await?,
+            None => {
+                let decoded = DecodedWitness::decode(&cache)?;
+                let store = decoded.build_store().await?;
+                if opts.reuse_storage {
+                    decoded_witness = Some(decoded);
+                }
+                store
+            }
+        };
+        let blockchain = no_zkvm_blockchain(store);
         let prep_duration = prep_start.elapsed();
         prep_durations.push(prep_duration);
 
         info!("Storage preparation finished in {:.2?}", prep_duration);
-        info!("Executing block {} on {}", block.header.number, network);
 
-        let exec_start = Instant::now();
-        blockchain.add_block_pipeline(block, None)?;
-        let exec_duration = exec_start.elapsed();
+        let mut exec_duration = Duration::ZERO;
+        for (block, durations) in cache.blocks.iter().zip(block_durations.iter_mut()) {
+            info!("Executing block {} on {}", block.header.number, network);
+
+            let block_start = Instant::now();
+            blockchain.add_block_pipeline(block.clone(), None)?;
+            let block_duration = block_start.elapsed();
+            durations.push(block_duration);
+            exec_duration += block_duration;
+        }
         exec_durations.push(exec_duration);
 
         if repeat == 1 {
@@ -1139,8 +1657,21 @@ async fn replay_no_zkvm(cache: Cache, opts: &EthrexReplayOptions) -> eyre::Resul
         }
     }
 
-    if repeat > 1 {
-        use crate::profiling::{RunStats, print_individual_runs};
+    if repeat > 1 && opts.reuse_storage {
+        let reused_prep_stats = RunStats::new(prep_durations[1..].to_vec());
+        let reused_exec_stats = RunStats::new(exec_durations[1..].to_vec());
+        info!("=== Profiling Results ({repeat} runs, decoded witness reused) ===");
+        info!(
+            "First run (decodes the witness): prep={:.2?} exec={:.2?}",
+            prep_durations[0], exec_durations[0]
+        );
+        info!("Preparation, decode reused (store still built from scratch):\n{reused_prep_stats}");
+        info!(
+            "Execution, decode reused (add_block_pipeline on a fresh store):\n{reused_exec_stats}"
+        );
+        info!("Individual runs (#1 decodes the witness):");
+        print_individual_runs(&prep_durations, &exec_durations);
+    } else if repeat > 1 {
         let prep_stats = RunStats::new(prep_durations.clone());
         let exec_stats = RunStats::new(exec_durations.clone());
         info!("=== Profiling Results ({repeat} runs) ===");
@@ -1148,14 +1679,88 @@ async fn replay_no_zkvm(cache: Cache, opts: &EthrexReplayOptions) -> eyre::Resul
         info!("Execution (add_block_pipeline):\n{exec_stats}");
         info!("Individual runs:");
         print_individual_runs(&prep_durations, &exec_durations);
-        Ok(exec_stats.median())
+    }
+
+    if let Some(path) = &opts.stats_out {
+        let stats = StatsExport {
+            network: network.clone(),
+            first_block: cache.get_first_block_number()?,
+            last_block: cache
+                .blocks
+                .last()
+                .map(|block| block.header.number)
+                .ok_or_eyre("missing block data")?,
+            git_commit: get_current_git_commit(),
+            prep: prep_durations.clone(),
+            exec: exec_durations.clone(),
+            reuse_storage: opts.reuse_storage,
+        };
+        stats.append_to(path)?;
+        info!("Appended run statistics to {}", path.display());
+    }
+
+    if cache.blocks.len() > 1 {
+        info!(
+            "Execution per block (add_block_pipeline{}):",
+            if repeat > 1 { ", median" } else { "" }
+        );
+        for (block, durations) in cache.blocks.iter().zip(block_durations) {
+            let duration = RunStats::new(durations).median();
+            info!(
+                "  Block {}: {:.2?} ({} txs, {} gas)",
+                block.header.number,
+                duration,
+                block.body.transactions.len(),
+                block.header.gas_used
+            );
+        }
+    }
+
+    if opts.tx_profile {
+        profile_txs(&cache).await?;
+    }
+
+    if repeat > 1 {
+        Ok(RunStats::new(exec_durations).median())
     } else {
         Ok(exec_durations[0])
     }
 }
 
+/// Executes every transaction of each block of the cache on its own with LEVM, as `run_txs`
+/// does, and logs them ranked by execution time, block by block.
+async fn profile_txs(cache: &Cache) -> eyre::Result<()> {
+    for (block_index, block) in cache.blocks.iter().enumerate() {
+        let block_number = block.header.number;
+        let tx_count = block.body.transactions.len();
+        if tx_count == 0 {
+            info!("Block {block_number} has no transactions to profile");
+            continue;
+        }
+
+        let tx_replays = run_txs_in_block(
+            cache.clone(),
+            block_index,
+            &TxSelection::Indices(0..tx_count),
+            &Overrides::default(),
+        )
+        .await?;
+
+        print_tx_profile(
+            block_number,
+            tx_replays.iter().map(TxProfile::from_replay).collect(),
+        );
+    }
+
+    Ok(())
+}
+
 async fn replay_transaction(tx_opts: TransactionOpts) -> eyre::Result<()> {
-    let tx_hash = tx_opts.tx_hash;
+    let selection = match (tx_opts.tx_hash, tx_opts.tx_index) {
+        (_, Some(range)) => TxSelection::Indices(range),
+        (Some(tx_hash), None) => TxSelection::Hash(tx_hash),
+        (None, None) => unreachable!("enforced by clap"),
+    };
 
     if tx_opts.opts.cached && tx_opts.block_number.is_none() {
         return Err(eyre::Error::msg(
@@ -1163,60 +1768,550 @@ async fn replay_transaction(tx_opts: TransactionOpts) -> eyre::Result<()> {
         ));
     }
 
+    let overrides = tx_opts.overrides.to_overrides()?;
+
     let cache = get_blockdata(tx_opts.opts, tx_opts.block_number).await?.0;
 
-    let (receipt, transitions) = run_tx(cache, tx_hash).await?;
+    if let Some(format) = tx_opts.call_trace {
+        if !overrides.is_empty() {
+            return Err(eyre::Error::msg(
+                "Overrides aren't supported together with --call-trace",
+            ));
+        }
 
-    print_receipt(receipt);
+        let TxSelection::Hash(tx_hash) = selection else {
+            unreachable!("--call-trace conflicts with --tx-index");
+        };
 
-    for transition in transitions {
-        print_transition(transition);
+        let trace = trace_tx_calls(cache, tx_hash).await?;
+        // There is a single top-level frame per transaction, like geth returns.
+        let trace = serde_json::to_value(
+            trace
+                .first()
+                .ok_or_eyre("call tracer returned an empty trace")?,
+        )?;
+        match format {
+            CallTraceFormat::Json => println!("{}", serde_json::to_string_pretty(&trace)?),
+            CallTraceFormat::Pretty => print_call_frame(&trace, 0),
+        }
+        return Ok(());
+    }
+
+    let tx_replays = run_txs(cache, &selection, &overrides).await?;
+
+    if tx_opts.output == OutputFormat::Json {
+        let json = match selection {
+            TxSelection::Hash(_) => tx_replay_json(&tx_replays[0]),
+            TxSelection::Indices(_) => {
+                serde_json::Value::Array(tx_replays.iter().map(tx_replay_json).collect())
+            }
+        };
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    if let TxSelection::Hash(_) = selection {
+        for tx_replay in tx_replays {
+            print_receipt(tx_replay.receipt);
+
+            for transition in tx_replay.transitions {
+                print_transition(transition);
+            }
+        }
+        return Ok(());
+    }
+
+    let summary: Vec<_> = tx_replays
+        .iter()
+        .map(|tx_replay| {
+            (
+                tx_replay.index,
+                tx_replay.tx_hash,
+                tx_replay.receipt.succeeded,
+                tx_replay.receipt.cumulative_gas_used,
+                tx_replay.execution_time,
+            )
+        })
+        .collect();
+
+    for tx_replay in tx_replays {
+        println!("Transaction {} ({:#x})", tx_replay.index, tx_replay.tx_hash);
+        print_receipt(tx_replay.receipt);
+
+        for transition in tx_replay.transitions {
+            print_transition(transition);
+        }
+        println!();
+    }
+
+    println!(
+        "{:>6}  {:<66}  {:<7}  {:>12}  {:>14}",
+        "Index", "Hash", "Status", "Gas Used", "Execution Time"
+    );
+    for (index, tx_hash, succeeded, gas_used, execution_time) in summary {
+        println!(
+            "{index:>6}  {:<66}  {:<7}  {gas_used:>12}  {:>14}",
+            format!("{tx_hash:#x}"),
+            if succeeded { "success" } else { "failure" },
+            format!("{execution_time:.3?}")
+        );
     }
 
     Ok(())
 }
 
-async fn replay_block(block_opts: BlockOptions) -> eyre::Result<()> {
+/// JSON document describing a replayed transaction, for `--output json`.
+///
+/// Its shape is relied upon by scripts and dashboards, so fields should only be added.
+fn tx_replay_json(tx_replay: &TxReplay) -> serde_json::Value {
+    let receipt = &tx_replay.receipt;
+
+    let logs: Vec<_> = receipt
+        .logs
+        .iter()
+        .map(|log| {
+            serde_json::json!({
+                "address": log.address,
+                "topics": log.topics,
+                "data": format!("0x{}", hex::encode(&log.data)),
+            })
+        })
+        .collect();
+
+    let state_transitions: Vec<_> = tx_replay
+        .transitions
+        .iter()
+        .map(|update| {
+            let pre_state = tx_replay.pre_state.get(&update.address);
+            let pre_account = pre_state.and_then(|pre_state| pre_state.account_state.as_ref());
+
+            let mut storage: Vec<_> = update.added_storage.iter().collect();
+            storage.sort_by_key(|(key, _)| **key);
+            let storage: Vec<_> = storage
+                .into_iter()
+                .map(|(key, value)| {
+                    serde_json::json!({
+                        "slot": key,
+                        "pre": pre_state
+                            .and_then(|pre_state| pre_state.storage.get(key))
+                            .copied()
+                            .unwrap_or_default(),
+                        "post": value,
+                    })
+                })
+                .collect();
+
+            serde_json::json!({
+                "address": update.address,
+                "removed": update.removed,
+                "existedBefore": pre_account.is_some(),
+                "balance": {
+                    "pre": pre_account.map(|account| account.balance).unwrap_or_default(),
+                    "post": update.info.as_ref().map(|info| info.balance),
+                },
+                "nonce": {
+                    "pre": pre_account.map(|account| account.nonce).unwrap_or_default(),
+                    "post": update.info.as_ref().map(|info| info.nonce),
+                },
+                "codeHash": {
+                    "pre": pre_account.map(|account| account.code_hash),
+                    "post": update.info.as_ref().map(|info| info.code_hash),
+                },
+                "code": update
+                    .code
+                    .as_ref()
+                    .map(|code| format!("0x{}", hex::encode(&code.bytecode))),
+                "storage": storage,
+            })
+        })
+        .collect();
+
+    // Gas is reset for every transaction, so the receipt holds the gas used by this one alone.
+    let gas_used = receipt.cumulative_gas_used;
+
+    serde_json::json!({
+        "transactionHash": tx_replay.tx_hash,
+        "blockNumber": tx_replay.block_number,
+        "index": tx_replay.index,
+        "executionTimeMs": tx_replay.execution_time.as_secs_f64() * 1e3,
+        "status": if receipt.succeeded { "success" } else { "failure" },
+        "type": format!("{:?}", receipt.tx_type),
+        "gas": {
+            "limit": tx_replay.gas_limit,
+            "used": gas_used,
+            "baseFeePerGas": tx_replay.base_fee_per_gas,
+            "effectiveGasPrice": tx_replay.effective_gas_price,
+            "fee": tx_replay
+                .effective_gas_price
+                .map(|price| price * U256::from(gas_used)),
+        },
+        "logs": logs,
+        "stateTransitions": state_transitions,
+    })
+}
+
+/// Executes the block transaction by transaction and stops at the first receipt that differs
+/// from the one returned by `eth_getBlockReceipts`, printing its trace and state transitions.
+#[cfg(not(feature = "l2"))]
+async fn replay_receipts(block_opts: BlockOptions) -> eyre::Result<()> {
     let opts = block_opts.opts;
 
-    let block = block_opts.block;
+    let rpc_url = opts
+        .rpc_url
+        .clone()
+        .ok_or_eyre("--rpc-url is required to fetch the canonical receipts")?;
+    let client = RpcClient::new(rpc_url, RpcAuth::new(&opts.rpc_auth)?);
 
-    let (cache, network) = get_blockdata(opts.clone(), block).await?;
+    let cache = get_blockdata(opts, block_opts.block).await?.0;
+    let block_number = cache.get_first_block_number()?;
 
-    let block =
-        cache.blocks.first().cloned().ok_or_else(|| {
-            eyre::Error::msg("no block found in the cache, this should never happen")
-        })?;
+    let receipts = get_block_receipts(&client, block_number).await?;
 
-    let backend = backend(&opts.common.zkvm)?;
+    let Some(divergence) = receipts_diff::first_divergent_receipt(cache, &receipts)? else {
+        println!(
+            "All {} receipts of block {block_number} match the canonical ones.",
+            receipts.len()
+        );
+        return Ok(());
+    };
 
-    let (execution_result, proving_result) = if opts.no_zkvm {
-        (Some(replay_no_zkvm(cache.clone(), &opts).await), None)
+    println!(
+        "Transaction {} ({:#x}) diverges from the canonical receipt:",
+        divergence.tx_index, divergence.tx_hash
+    );
+    for field in &divergence.fields {
+        println!("  {field}");
+    }
+
+    if let Some(trace) = divergence.trace {
+        println!("Call trace:");
+        println!("{}", serde_json::to_string_pretty(&trace)?);
+    }
+
+    if !divergence.transitions.is_empty() {
+        println!("State transitions:");
+    }
+    for transition in divergence.transitions {
+        print_transition(transition);
+    }
+
+    Ok(())
+}
+
+/// Executes the block with every backend compiled in and flags any disagreement, either in
+/// whether execution succeeded or in the guest program outputs.
+#[cfg(not(feature = "l2"))]
+async fn compare_backends(block_opts: BlockOptions) -> eyre::Result<()> {
+    let cache = get_blockdata(block_opts.opts, block_opts.block).await?.0;
+    let block_number = cache.get_first_block_number()?;
+
+    info!(
+        "Executing block {block_number} with backends {:?}",
+        compiled_backends()
+    );
+
+    let executions = exec_all_backends(cache).await;
+
+    for execution in &executions {
+        match &execution.result {
+            Ok(Some(output)) => {
+                println!("{:?}: success", execution.backend);
+                for (field, value) in program_output_fields(output) {
+                    println!("  {field}: {value}");
+                }
+            }
+            Ok(None) => println!(
+                "{:?}: success, output not comparable (the backend doesn't return it)",
+                execution.backend
+            ),
+            Err(e) => println!("{:?}: failed: {e}", execution.backend),
+        }
+    }
+
+    let mut disagreements = Vec::new();
+
+    let (succeeded, failed): (Vec<_>, Vec<_>) = executions
+        .iter()
+        .partition(|execution| execution.result.is_ok());
+    if !succeeded.is_empty() && !failed.is_empty() {
+        disagreements.push(format!(
+            "{:?} failed while {:?} succeeded",
+            failed.iter().map(|e| e.backend).collect::<Vec<_>>(),
+            succeeded.iter().map(|e| e.backend).collect::<Vec<_>>()
+        ));
+    }
+
+    let (outputs, not_comparable): (Vec<_>, Vec<_>) = succeeded
+        .iter()
+        .partition(|execution| matches!(execution.result, Ok(Some(_))));
+    let outputs: Vec<_> = outputs
+        .iter()
+        .filter_map(|execution| match &execution.result {
+            Ok(Some(output)) => Some((execution.backend, program_output_fields(output))),
+            _ => None,
+        })
+        .collect();
+    if let Some(((reference_backend, reference), others)) = outputs.split_first() {
+        for (backend, fields) in others {
+            for ((field, expected), (_, value)) in reference.iter().zip(fields) {
+                if expected != value {
+                    disagreements.push(format!(
+                        "{backend:?} {field} is {value}, {reference_backend:?} got {expected}"
+                    ));
+                }
+            }
+        }
+    }
+
+    if !disagreements.is_empty() {
+        for disagreement in &disagreements {
+            println!("Disagreement: {disagreement}");
+        }
+        return Err(eyre::eyre!(
+            "Backends disagree on the execution of block {block_number}"
+        ));
+    }
+
+    if not_comparable.is_empty() {
+        println!(
+            "All {} backends agree on block {block_number}",
+            executions.len()
+        );
     } else {
-        match opts.common.action {
-            Action::Execute => {
-                let execution_result = exec(backend, cache.clone()).await;
+        println!(
+            "All {} backends succeeded on block {block_number}, outputs of {:?} agree, {:?} not comparable",
+            executions.len(),
+            outputs
+                .iter()
+                .map(|(backend, _)| *backend)
+                .collect::<Vec<_>>(),
+            not_comparable.iter().map(|e| e.backend).collect::<Vec<_>>()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "l2"))]
+fn program_output_fields(output: &GuestOutput) -> [(&'static str, String); 4] {
+    [
+        (
+            "initial state hash",
+            format!("{:#x}", output.initial_state_hash),
+        ),
+        (
+            "final state hash",
+            format!("{:#x}", output.final_state_hash),
+        ),
+        ("last block hash", format!("{:#x}", output.last_block_hash)),
+        ("chain id", output.chain_id.to_string()),
+    ]
+}
+
+/// Fetches, runs and reports a single block. With `isolate`, the block is executed or proven
+/// in a worker process so that crashes don't take down the `blocks` loop.
+async fn replay_block(block_opts: BlockOptions, isolate: bool) -> eyre::Result<()> {
+    let opts = block_opts.opts;
+
+    let block_run = fetch_and_run_block(&opts, block_opts.block, isolate).await?;
+
+    report_block_run(&opts, block_run).await
+}
 
-                (Some(execution_result), None)
+/// Replays the blocks `jobs` at a time, each on a task of its own, reporting them in order as
+/// they finish.
+#[cfg(not(feature = "l2"))]
+async fn replay_blocks_concurrently(
+    blocks: Vec<u64>,
+    opts: &EthrexReplayOptions,
+    jobs: usize,
+    isolate: bool,
+) -> eyre::Result<()> {
+    if opts.common.action != Action::Execute {
+        return Err(eyre::Error::msg(
+            "--jobs is only supported with --action execute",
+        ));
+    }
+
+    info!("Executing {} blocks, {jobs} at a time", blocks.len());
+
+    // The blocks share the header caches and, unless isolated, the process whose usage is
+    // measured, so neither can be scoped to a single block anymore.
+    keep_ancestor_headers();
+    disable_process_usage();
+
+    // A block holds its permit until it's reported, so no more than `jobs` blocks are held in
+    // memory while an earlier one is still running.
+    let permits = Arc::new(Semaphore::new(jobs));
+    let mut running = VecDeque::new();
+
+    for block in blocks {
+        let permit = match permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                report_next_block_run(opts, &mut running).await?;
+                permits.clone().acquire_owned().await?
             }
-            Action::Prove => {
-                // Always execute before proving, unless it's ZisK.
-                // This is because of ZisK's client initializing MPI, which can't be done
-                // more than once in the same process.
-                // https://docs.open-mpi.org/en/v5.0.1/man-openmpi/man3/MPI_Init_thread.3.html#description
-                #[cfg(not(feature = "zisk"))]
-                let execution_result = Some(exec(backend, cache.clone()).await);
-                #[cfg(feature = "zisk")]
-                let execution_result = None;
-
-                let proving_result = prove(backend, opts.common.proof_type, cache.clone()).await;
-
-                (execution_result, Some(proving_result))
+        };
+
+        let opts = opts.clone();
+        running.push_back(tokio::spawn(async move {
+            let block_run = fetch_and_run_block(&opts, Some(block), isolate).await;
+            (block_run, permit)
+        }));
+    }
+
+    while !running.is_empty() {
+        report_next_block_run(opts, &mut running).await?;
+    }
+
+    Ok(())
+}
+
+/// Waits for the earliest block still running and reports it, releasing its permit.
+#[cfg(not(feature = "l2"))]
+async fn report_next_block_run(
+    opts: &EthrexReplayOptions,
+    running: &mut VecDeque<JoinHandle<(eyre::Result<BlockRun>, OwnedSemaphorePermit)>>,
+) -> eyre::Result<()> {
+    let Some(task) = running.pop_front() else {
+        return Ok(());
+    };
+    let (block_run, _permit) = task
+        .await
+        .map_err(|e| eyre::eyre!("Block task failed: {e}"))?;
+    report_block_run(opts, block_run?).await
+}
+
+/// A block that was fetched and run, waiting to be reported.
+enum BlockRun {
+    Ran {
+        cache: Cache,
+        network: Network,
+        outcome: BlockOutcome,
+    },
+    FetchTimedOut {
+        block_number: u64,
+        err: eyre::Report,
+    },
+}
+
+/// Fetches the block data and executes and/or proves it, in a worker process with `isolate`.
+async fn fetch_and_run_block(
+    opts: &EthrexReplayOptions,
+    block: Option<u64>,
+    isolate: bool,
+) -> eyre::Result<BlockRun> {
+    // A timed out block is reported for the network, which can't be asked to the datadir's
+    // node without fetching again.
+    if opts.datadir.is_some() && opts.fetch_timeout.is_some() && opts.network.is_none() {
+        return Err(eyre::eyre!(
+            "--fetch-timeout requires --network when reading blocks from --datadir"
+        ));
+    }
+
+    let fetch_timeout = opts.fetch_timeout.map(Duration::from_secs);
+    let fetch_usage = UsageRecorder::new(matches!(opts.common.resource, Resource::GPU));
+    let fetch = with_timeout(
+        Phase::Fetch,
+        fetch_timeout,
+        get_blockdata(opts.clone(), block),
+    );
+    let (cache, network) = match fetch_usage.measure_async(UsagePhase::Fetch, fetch).await {
+        Ok(blockdata) => blockdata,
+        Err(err) => match block {
+            Some(block_number) if is_timeout(&err) => {
+                return Ok(BlockRun::FetchTimedOut { block_number, err });
             }
+            _ => return Err(err),
+        },
+    };
+
+    let mut outcome = if isolate {
+        run_in_worker(&cache, &opts.common).await
+    } else {
+        run_block(opts, cache.clone()).await?
+    };
+    outcome.usage.fetch = fetch_usage.usage().fetch;
+
+    Ok(BlockRun::Ran {
+        cache,
+        network,
+        outcome,
+    })
+}
+
+async fn report_block_run(opts: &EthrexReplayOptions, block_run: BlockRun) -> eyre::Result<()> {
+    match block_run {
+        BlockRun::Ran {
+            cache,
+            network,
+            outcome,
+        } => report_block(opts.clone(), cache, network, outcome).await,
+        BlockRun::FetchTimedOut { block_number, err } => {
+            report_fetch_timeout(opts, block_number, err).await
         }
+    }
+}
+
+/// Executes and/or proves the block in the cache according to the options.
+async fn run_block(opts: &EthrexReplayOptions, cache: Cache) -> eyre::Result<BlockOutcome> {
+    let backend = backend(&opts.common.zkvm)?;
+    let usage = UsageRecorder::new(matches!(opts.common.resource, Resource::GPU));
+
+    if opts.no_zkvm {
+        let execution_result = usage
+            .measure_async(UsagePhase::Execution, replay_no_zkvm(cache, opts))
+            .await;
+
+        return Ok(BlockOutcome {
+            execution_result: Some(execution_result),
+            proving_result: None,
+            cycles: None,
+            public_values: None,
+            usage: usage.usage(),
+        });
+    }
+
+    Ok(exec_and_prove(backend, &cache, &opts.common, &usage).await)
+}
+
+/// Builds the report of a run block, then logs, notifies and benchmarks it and decides whether
+/// to keep its cache.
+async fn report_block(
+    opts: EthrexReplayOptions,
+    cache: Cache,
+    network: Network,
+    outcome: BlockOutcome,
+) -> eyre::Result<()> {
+    let BlockOutcome {
+        execution_result,
+        proving_result,
+        cycles,
+        public_values,
+        usage,
+    } = outcome;
+
+    let block =
+        cache.blocks.first().cloned().ok_or_else(|| {
+            eyre::Error::msg("no block found in the cache, this should never happen")
+        })?;
+
+    let fetch_timeout = opts.fetch_timeout.map(Duration::from_secs);
+    let state_diff = if opts.state_diff && matches!(execution_result, Some(Err(_))) {
+        with_timeout(
+            Phase::Fetch,
+            fetch_timeout,
+            get_state_diff(&opts, cache.clone(), &network),
+        )
+        .await
+        .inspect_err(|e| warn!("Failed to compute state diff: {e}"))
+        .ok()
+    } else {
+        None
     };
 
-    let report = Report::new_for(
+    let mut report = Report::new_for(
         opts.common.zkvm,
         opts.common.resource,
         opts.common.action,
@@ -1225,24 +2320,17 @@ async fn replay_block(block_opts: BlockOptions) -> eyre::Result<()> {
         execution_result,
         proving_result,
     );
-
-    if opts.common.verbose {
-        println!("{report}");
-    } else {
-        report.log();
+    report.state_diff = state_diff;
+    report.set_cycles(cycles, &cache.blocks);
+    report.resource_usage = usage;
+    // `add_block_pipeline` already checks the resulting state root.
+    if !opts.no_zkvm {
+        verify_output(&cache, public_values.as_deref(), &mut report);
     }
 
-    match opts.notification_level {
-        NotificationLevel::Failed => {
-            if report.has_error() {
-                try_send_report_to_slack(&report, opts.slack_webhook_url).await?;
-            }
-        }
-        NotificationLevel::Off => {}
-        NotificationLevel::On => {
-            try_send_report_to_slack(&report, opts.slack_webhook_url).await?;
-        }
-    };
+    print_report(&report, &opts.common)?;
+
+    notify(&report, &opts).await?;
 
     // Decide whether or not to keep the cache when fetching data from RPC.
     if !opts.cached {
@@ -1276,6 +2364,230 @@ async fn replay_block(block_opts: BlockOptions) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Prints the report in the format given by `--report-format`.
+fn print_report(report: &Report, opts: &CommonOptions) -> eyre::Result<()> {
+    match opts.report_format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report.to_json())?),
+        OutputFormat::Text if opts.verbose => println!("{report}"),
+        OutputFormat::Text => report.log(),
+    }
+    Ok(())
+}
+
+/// Sends the report to Slack according to `--notification-level`.
+async fn notify(report: &Report, opts: &EthrexReplayOptions) -> eyre::Result<()> {
+    match opts.notification_level {
+        NotificationLevel::Failed => {
+            if report.has_error() {
+                try_send_report_to_slack(report, opts.slack_webhook_url.clone()).await?;
+            }
+        }
+        NotificationLevel::Off => {}
+        NotificationLevel::On => {
+            try_send_report_to_slack(report, opts.slack_webhook_url.clone()).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Reports a block whose data couldn't be fetched in time, instead of failing, so `blocks`
+/// moves on to the next one.
+async fn report_fetch_timeout(
+    opts: &EthrexReplayOptions,
+    block_number: u64,
+    err: eyre::Report,
+) -> eyre::Result<()> {
+    let network = match &opts.network {
+        Some(network) => network.clone(),
+        None if opts.rpc_url.is_none() => {
+            return Err(err.wrap_err(format!(
+                "Can't report block {block_number}: --network is required without --rpc-url"
+            )));
+        }
+        None => {
+            let fetch_timeout = opts.fetch_timeout.map(Duration::from_secs);
+            with_timeout(Phase::Fetch, fetch_timeout, setup_rpc(opts))
+                .await?
+                .1
+        }
+    };
+
+    let block = Block::new(
+        BlockHeader {
+            number: block_number,
+            ..Default::default()
+        },
+        BlockBody::empty(),
+    );
+
+    let report = Report::new_for(
+        opts.common.zkvm.clone(),
+        opts.common.resource.clone(),
+        opts.common.action.clone(),
+        block,
+        network,
+        Some(Err(err)),
+        None,
+    );
+
+    print_report(&report, &opts.common)?;
+
+    notify(&report, opts).await
+}
+
+/// Executes the block on its own thread, giving up after `--execute-timeout`.
+async fn exec_with_timeout(
+    backend: BackendType,
+    cache: Cache,
+    opts: &CommonOptions,
+    usage: &UsageRecorder,
+) -> eyre::Result<Execution> {
+    let timeout = opts.execute_timeout.map(Duration::from_secs);
+    let execution = exec_with_output(backend, cache, usage.clone());
+    with_blocking_timeout(Phase::Execution, timeout, execution).await?
+}
+
+/// Executes and/or proves the blocks of the cache with the backend, according to `--action`.
+async fn exec_and_prove(
+    backend: BackendType,
+    cache: &Cache,
+    opts: &CommonOptions,
+    usage: &UsageRecorder,
+) -> BlockOutcome {
+    let (execution_result, proving_result) = match opts.action {
+        Action::Execute => (
+            Some(exec_with_timeout(backend, cache.clone(), opts, usage).await),
+            None,
+        ),
+        Action::Prove => {
+            // Always execute before proving, unless it's ZisK.
+            // This is because of ZisK's client initializing MPI, which can't be done
+            // more than once in the same process.
+            // https://docs.open-mpi.org/en/v5.0.1/man-openmpi/man3/MPI_Init_thread.3.html#description
+            #[cfg(not(feature = "zisk"))]
+            let execution_result =
+                Some(exec_with_timeout(backend, cache.clone(), opts, usage).await);
+            #[cfg(feature = "zisk")]
+            let execution_result = None;
+
+            let proving_result = prove_with_timeout(backend, cache.clone(), opts, usage).await;
+
+            (execution_result, Some(proving_result))
+        }
+    };
+
+    let (cycles, execution_public_values) = match &execution_result {
+        Some(Ok(execution)) => (execution.cycles, execution.public_values.clone()),
+        _ => (None, None),
+    };
+    // The proof is what gets saved, so check what it commits to when it's available.
+    let public_values = match &proving_result {
+        Some(Ok(Proving {
+            public_values: Some(public_values),
+            ..
+        })) => Some(public_values.clone()),
+        _ => execution_public_values,
+    };
+
+    BlockOutcome {
+        execution_result: execution_result.map(|result| result.map(|execution| execution.elapsed)),
+        proving_result: proving_result.map(|result| result.map(|proving| proving.elapsed)),
+        cycles,
+        public_values,
+        usage: usage.usage(),
+    }
+}
+
+/// Proves the block on its own thread, giving up after `--prove-timeout`.
+async fn prove_with_timeout(
+    backend: BackendType,
+    cache: Cache,
+    opts: &CommonOptions,
+    usage: &UsageRecorder,
+) -> eyre::Result<Proving> {
+    let timeout = opts.prove_timeout.map(Duration::from_secs);
+    let proving = prove(
+        backend,
+        opts.proof_type.clone(),
+        cache,
+        opts.proofs_dir.clone(),
+        usage.clone(),
+    );
+    with_blocking_timeout(Phase::Proving, timeout, proving).await?
+}
+
+/// Verifies a proof saved by `prove` with the backend that generated it.
+fn verify_proof(path: &Path) -> eyre::Result<()> {
+    let saved_proof = SavedProof::load(path)?;
+
+    let blocks = if saved_proof.first_block == saved_proof.last_block {
+        format!("block {}", saved_proof.first_block)
+    } else {
+        format!(
+            "blocks {} to {}",
+            saved_proof.first_block, saved_proof.last_block
+        )
+    };
+
+    info!(
+        "Verifying {} proof of {blocks} on {} with {}",
+        saved_proof.proof_type, saved_proof.network, saved_proof.backend
+    );
+
+    let backend = saved_proof.verify()?;
+
+    println!(
+        "✅ {} proof of {blocks} on {} (last block hash {:#x}) verified with {backend:?}",
+        saved_proof.proof_type, saved_proof.network, saved_proof.last_block_hash
+    );
+
+    Ok(())
+}
+
+/// Checks the guest program output committed by the backend against the cached headers if the
+/// block was executed or proven, as neither checks it against the chain. Skipped for backends
+/// that don't return the output.
+fn verify_output(cache: &Cache, public_values: Option<&[u8]>, report: &mut Report) {
+    let replayed = matches!(report.execution_result, Some(Ok(_)))
+        || matches!(report.proving_result, Some(Ok(_)));
+    if let (true, Some(public_values)) = (replayed, public_values) {
+        report.output_verification = Some(output_verification::verify_program_output(
+            cache,
+            public_values,
+        ));
+    }
+}
+
+/// Diffs the post-state computed by LEVM against the canonical one, read from the RPC if
+/// there is one, or from the cached witness of the next block otherwise.
+async fn get_state_diff(
+    opts: &EthrexReplayOptions,
+    cache: Cache,
+    network: &Network,
+) -> eyre::Result<StateDiff> {
+    let block_number = cache.get_first_block_number()?;
+
+    let post_state = match &opts.rpc_url {
+        Some(rpc_url) => PostState::Rpc {
+            client: RpcClient::new(rpc_url.clone(), RpcAuth::new(&opts.rpc_auth)?),
+            block_number,
+        },
+        None => {
+            let file_name = get_block_cache_file_name(network, block_number + 1, None);
+            let next_block_cache = Cache::load(&opts.cache_dir, &file_name).wrap_err(format!(
+                "The post-state of block {block_number} is read from the cached witness of block {}, which wasn't found",
+                block_number + 1
+            ))?;
+            PostState::from_next_block_cache(next_block_cache)?
+        }
+    };
+
+    info!("Computing state diff for block {block_number}");
+
+    state_diff::diff_post_state(cache, &post_state).await
+}
+
 pub fn backend(zkvm: &Option<ZKVM>) -> eyre::Result<BackendType> {
     match zkvm {
         Some(ZKVM::SP1) => {
@@ -1347,6 +2659,36 @@ fn print_transition(update: AccountUpdate) {
     }
 }
 
+/// Prints a `callTracer` frame and its subcalls as an indented tree.
+fn print_call_frame(frame: &serde_json::Value, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let field = |name: &str| frame[name].as_str().unwrap_or_default().to_string();
+
+    println!(
+        "{indent}{} {} -> {} (value: {}, gas: {}, gas used: {})",
+        field("type"),
+        field("from"),
+        field("to"),
+        frame["value"].as_str().unwrap_or("0x0"),
+        field("gas"),
+        field("gasUsed")
+    );
+    println!("{indent}  input: {}", field("input"));
+    if frame["output"].is_string() {
+        println!("{indent}  output: {}", field("output"));
+    }
+    if frame["error"].is_string() {
+        println!("{indent}  error: {}", field("error"));
+    }
+    if frame["revertReason"].is_string() {
+        println!("{indent}  revert reason: {}", field("revertReason"));
+    }
+
+    for call in frame["calls"].as_array().into_iter().flatten() {
+        print_call_frame(call, depth + 1);
+    }
+}
+
 fn print_receipt(receipt: Receipt) {
     if receipt.succeeded {
         println!("Transaction succeeded.")
@@ -1426,30 +2768,17 @@ pub async fn replay_custom_l1_blocks(
     }
 
     let backend = backend(&opts.common.zkvm)?;
+    let usage = UsageRecorder::new(matches!(opts.common.resource, Resource::GPU));
 
-    let (execution_result, proving_result) = match opts.common.action {
-        Action::Execute => {
-            let execution_result = exec(backend, cache.clone()).await;
-
-            (Some(execution_result), None)
-        }
-        Action::Prove => {
-            // Always execute before proving, unless it's ZisK.
-            // This is because of ZisK's client initializing MPI, which can't be done
-            // more than once in the same process.
-            // https://docs.open-mpi.org/en/v5.0.1/man-openmpi/man3/MPI_Init_thread.3.html#description
-            #[cfg(not(feature = "zisk"))]
-            let execution_result = Some(exec(backend, cache.clone()).await);
-            #[cfg(feature = "zisk")]
-            let execution_result = None;
-
-            let proving_result = prove(backend, opts.common.proof_type, cache.clone()).await;
-
-            (execution_result, Some(proving_result))
-        }
-    };
+    let BlockOutcome {
+        execution_result,
+        proving_result,
+        cycles,
+        public_values,
+        usage,
+    } = exec_and_prove(backend, &cache, &opts.common, &usage).await;
 
-    let report = Report::new_for(
+    let mut report = Report::new_for(
         opts.common.zkvm,
         opts.common.resource,
         opts.common.action,
@@ -1460,12 +2789,11 @@ pub async fn replay_custom_l1_blocks(
         execution_result,
         proving_result,
     );
+    report.set_cycles(cycles, &cache.blocks);
+    report.resource_usage = usage;
+    verify_output(&cache, public_values.as_deref(), &mut report);
 
-    if opts.common.verbose {
-        println!("{report}");
-    } else {
-        report.log();
-    }
+    print_report(&report, &opts.common)?;
 
     Ok(())
 }
@@ -1681,35 +3009,22 @@ pub async fn replay_custom_l2_blocks(
     }
 
     let backend = backend(&opts.common.zkvm)?;
+    let usage = UsageRecorder::new(matches!(opts.common.resource, Resource::GPU));
 
-    let (execution_result, proving_result) = match opts.common.action {
-        Action::Execute => {
-            let execution_result = exec(backend, cache.clone()).await;
-
-            (Some(execution_result), None)
-        }
-        Action::Prove => {
-            // Always execute before proving, unless it's ZisK.
-            // This is because of ZisK's client initializing MPI, which can't be done
-            // more than once in the same process.
-            // https://docs.open-mpi.org/en/v5.0.1/man-openmpi/man3/MPI_Init_thread.3.html#description
-            #[cfg(not(feature = "zisk"))]
-            let execution_result = Some(exec(backend, cache.clone()).await);
-            #[cfg(feature = "zisk")]
-            let execution_result = None;
-
-            let proving_result = prove(backend, opts.common.proof_type, cache.clone()).await;
-
-            (execution_result, Some(proving_result))
-        }
-    };
+    let BlockOutcome {
+        execution_result,
+        proving_result,
+        cycles,
+        public_values,
+        usage,
+    } = exec_and_prove(backend, &cache, &opts.common, &usage).await;
 
     let report_block =
         cache.blocks.last().cloned().ok_or_else(|| {
             eyre::Error::msg("no block found in the cache, this should never happen")
         })?;
 
-    let report = Report::new_for(
+    let mut report = Report::new_for(
         opts.common.zkvm,
         opts.common.resource,
         opts.common.action,
@@ -1718,12 +3033,11 @@ pub async fn replay_custom_l2_blocks(
         execution_result,
         proving_result,
     );
+    report.set_cycles(cycles, &cache.blocks);
+    report.resource_usage = usage;
+    verify_output(&cache, public_values.as_deref(), &mut report);
 
-    if opts.common.verbose {
-        println!("{report}");
-    } else {
-        report.log();
-    }
+    print_report(&report, &opts.common)?;
 
     Ok(())
 }
@@ -1875,12 +3189,10 @@ pub async fn produce_custom_l2_block(
 
 #[cfg(not(feature = "l2"))]
 async fn fetch_latest_block_number(
-    rpc_url: Url,
+    client: &RpcClient,
     only_eth_proofs_blocks: bool,
 ) -> eyre::Result<u64> {
-    let eth_client = EthClient::new(rpc_url)?;
-
-    let mut latest_block_number = eth_client.get_block_number().await?.as_u64();
+    let mut latest_block_number = get_block_number(client).await?;
 
     while only_eth_proofs_blocks && latest_block_number % 100 != 0 {
         let blocks_left_for_next_eth_proofs_block = 100 - (latest_block_number % 100);
@@ -1897,12 +3209,33 @@ async fn fetch_latest_block_number(
 
         tokio::time::sleep(time_for_next_eth_proofs_block).await;
 
-        latest_block_number = eth_client.get_block_number().await?.as_u64();
+        latest_block_number = get_block_number(client).await?;
     }
 
     Ok(latest_block_number)
 }
 
+/// Waits for the chain to advance and returns the latest block number, either from the
+/// `newHeads` subscription if there is one or by polling the RPC.
+#[cfg(not(feature = "l2"))]
+async fn wait_for_latest_block_number(
+    client: &RpcClient,
+    new_heads: &mut Option<NewHeads>,
+    only_eth_proofs_blocks: bool,
+) -> eyre::Result<u64> {
+    match new_heads {
+        Some(new_heads) => new_heads.next().await,
+        None => {
+            let latest_block_number =
+                fetch_latest_block_number(client, only_eth_proofs_blocks).await?;
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            Ok(latest_block_number)
+        }
+    }
+}
+
 #[cfg(not(feature = "l2"))]
 fn format_duration(duration: &Duration) -> String {
     let total_seconds = duration.as_secs();
@@ -1,23 +1,29 @@
 use ethrex_config::networks::Network;
 use ethrex_levm::vm::VMType;
-use ethrex_rpc::{
-    EthClient,
-    clients::EthClientError,
-    types::block_identifier::{BlockIdentifier, BlockTag},
-};
+use ethrex_rpc::types::block_identifier::{BlockIdentifier, BlockTag};
 use eyre::{OptionExt, WrapErr};
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 use tracing::{debug, info, warn};
 
 use crate::{
-    cache::{Cache, get_block_cache_file_name},
+    cache::{Cache, get_block_cache_file_name, get_checkpoint_file_name},
     cli::{EthrexReplayOptions, setup_rpc},
-    rpc::db::RpcDB,
+    rpc::{
+        JsonRpcError, RpcClient, db::RpcDB, get_block, get_block_number, get_chain_id, get_witness,
+        retry,
+    },
 };
 
+#[cfg(feature = "rocksdb")]
+use ethrex_blockchain::{Blockchain, BlockchainOptions};
+#[cfg(feature = "rocksdb")]
+use ethrex_common::types::block_execution_witness::RpcExecutionWitness;
+#[cfg(feature = "rocksdb")]
+use ethrex_storage::{EngineType, Store};
+
 #[cfg(feature = "l2")]
 use crate::cache::L2Fields;
 #[cfg(feature = "l2")]
@@ -41,8 +47,25 @@ pub async fn get_blockdata(
             eyre::eyre!("Cache wasn't found for block {requested_block_number}: {e}")
         })?;
         Ok((cache, network))
+    } else if let Some(datadir) = &opts.datadir {
+        let cache = get_blockdata_datadir(datadir, block, opts.cache_dir.clone()).await?;
+        if let Some(network) = &opts.network
+            && network != &cache.network
+        {
+            return Err(eyre::eyre!(
+                "Specified network ({}) does not match datadir network ({})",
+                network,
+                cache.network
+            ));
+        }
+
+        // Written for the same reasons as when fetching from RPC.
+        cache.write()?;
+
+        let network = cache.network.clone();
+        Ok((cache, network))
     } else {
-        let (eth_client, rpc_network) = setup_rpc(&opts).await?;
+        let (client, rpc_network) = setup_rpc(&opts).await?;
         if let Some(network) = &opts.network
             && network != &rpc_network
         {
@@ -57,7 +80,7 @@ pub async fn get_blockdata(
             None => BlockIdentifier::Tag(BlockTag::Latest),
         };
         let cache = get_blockdata_rpc(
-            eth_client,
+            client,
             rpc_network.clone(),
             block_identifier,
             opts.cache_dir.clone(),
@@ -72,14 +95,165 @@ pub async fn get_blockdata(
     }
 }
 
+/// Retrieves data from the database of a local ethrex node, without any network calls.
+///
+/// The witness is generated by re-executing the block on top of its parent state, so the
+/// parent state must still be available in the node's database.
+///
+/// ethrex's `Store` only opens databases read-write and takes the RocksDB lock, so it never
+/// opens the node's database. Instead, a RocksDB checkpoint of it is taken under `cache_dir`
+/// and opened, which works while the node is running and leaves its database untouched.
+#[cfg(feature = "rocksdb")]
+async fn get_blockdata_datadir(
+    datadir: &Path,
+    block: Option<u64>,
+    cache_dir: PathBuf,
+) -> eyre::Result<Cache> {
+    if !datadir.join("CURRENT").is_file() {
+        return Err(eyre::eyre!(
+            "{} doesn't exist or is not the datadir of an ethrex node",
+            datadir.display()
+        ));
+    }
+
+    let checkpoint = DatadirCheckpoint::take(datadir, &cache_dir)?;
+    let store = Store::new(&checkpoint.path, EngineType::RocksDB)
+        .wrap_err_with(|| format!("Failed to open ethrex store at {}", datadir.display()))?;
+
+    let requested_block_number = match block {
+        Some(n) => n,
+        None => store.get_latest_block_number().await?,
+    };
+
+    info!(
+        "Retrieving execution data for block {requested_block_number} from {}",
+        datadir.display()
+    );
+
+    let block = store
+        .get_block_by_number(requested_block_number)
+        .await?
+        .ok_or_eyre(format!(
+            "Block {requested_block_number} not found in {}",
+            datadir.display()
+        ))?;
+
+    let blockchain = Blockchain::new(store, BlockchainOptions::default());
+
+    let execution_witness_generation_start_time = SystemTime::now();
+
+    let execution_witness = blockchain
+        .generate_witness_for_blocks(std::slice::from_ref(&block))
+        .await
+        .wrap_err("Failed to generate execution witness, the parent state may have been pruned")?;
+
+    let execution_witness_generation_duration = execution_witness_generation_start_time
+        .elapsed()
+        .unwrap_or_else(|e| {
+            panic!("SystemTime::elapsed failed: {e}");
+        });
+
+    debug!(
+        "Generated execution witness for block {requested_block_number} in {}",
+        format_duration(&execution_witness_generation_duration)
+    );
+
+    let chain_config = execution_witness.chain_config;
+
+    Ok(Cache::new(
+        vec![block],
+        RpcExecutionWitness::try_from(execution_witness)?,
+        chain_config,
+        cache_dir,
+    ))
+}
+
+/// RocksDB checkpoint of a datadir, deleted when dropped.
+///
+/// The node's database is opened as a secondary instance, which doesn't take its lock and
+/// only reads it, and caught up with the node. A checkpoint of it is then created, which
+/// is a consistent view of the database at that point: table files are hard-linked when on
+/// the same filesystem and the rest is copied.
+#[cfg(feature = "rocksdb")]
+struct DatadirCheckpoint {
+    path: PathBuf,
+    /// Directory where the secondary instance keeps its own info logs.
+    secondary_path: PathBuf,
+}
+
+/// Number of datadir checkpoints taken so far by the process, so that blocks fetched
+/// concurrently with `--jobs` each take theirs in a directory of its own.
+#[cfg(feature = "rocksdb")]
+static DATADIR_CHECKPOINTS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[cfg(feature = "rocksdb")]
+impl DatadirCheckpoint {
+    fn take(datadir: &Path, cache_dir: &Path) -> eyre::Result<Self> {
+        let id = format!(
+            "{}_{}",
+            std::process::id(),
+            DATADIR_CHECKPOINTS.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        );
+        let checkpoint = Self {
+            path: cache_dir.join(format!("datadir_checkpoint_{id}")),
+            secondary_path: cache_dir.join(format!("datadir_secondary_{id}")),
+        };
+        let _ = std::fs::remove_dir_all(&checkpoint.path);
+        std::fs::create_dir_all(&checkpoint.secondary_path)?;
+
+        let mut options = rocksdb::Options::default();
+        // Secondary instances must keep every table file open.
+        options.set_max_open_files(-1);
+
+        let column_families = rocksdb::DB::list_cf(&options, datadir)
+            .wrap_err_with(|| format!("Failed to list column families of {}", datadir.display()))?;
+        let db = rocksdb::DB::open_cf_as_secondary(
+            &options,
+            datadir,
+            &checkpoint.secondary_path,
+            column_families,
+        )
+        .wrap_err_with(|| format!("Failed to open {} as a secondary", datadir.display()))?;
+        db.try_catch_up_with_primary()?;
+
+        rocksdb::checkpoint::Checkpoint::new(&db)
+            .and_then(|checkpoint_builder| checkpoint_builder.create_checkpoint(&checkpoint.path))
+            .wrap_err_with(|| format!("Failed to take a checkpoint of {}", datadir.display()))?;
+
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Drop for DatadirCheckpoint {
+    fn drop(&mut self) {
+        for path in [&self.path, &self.secondary_path] {
+            if let Err(e) = std::fs::remove_dir_all(path) {
+                warn!("Failed to remove {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "rocksdb"))]
+async fn get_blockdata_datadir(
+    _datadir: &Path,
+    _block: Option<u64>,
+    _cache_dir: PathBuf,
+) -> eyre::Result<Cache> {
+    Err(eyre::eyre!(
+        "Reading from a datadir requires building ethrex-replay with the `rocksdb` feature"
+    ))
+}
+
 /// Retrieves data from RPC
 async fn get_blockdata_rpc(
-    eth_client: EthClient,
+    client: RpcClient,
     network: Network,
     block_identifier: BlockIdentifier,
     cache_dir: PathBuf,
 ) -> eyre::Result<Cache> {
-    let latest_block_number = eth_client.get_block_number().await?.as_u64();
+    let latest_block_number = get_block_number(&client).await?;
 
     let requested_block_number = match block_identifier {
         BlockIdentifier::Number(some_number) => some_number,
@@ -104,9 +278,9 @@ async fn get_blockdata_rpc(
 
     debug!("Validating RPC chain ID");
 
-    let chain_id = eth_client.get_chain_id().await?;
+    let chain_id = get_chain_id(&client).await?;
 
-    if chain_id != chain_config.chain_id.into() {
+    if chain_id != chain_config.chain_id {
         return Err(eyre::eyre!(
             "Rpc endpoint returned a different chain id than the one set by --network"
         ));
@@ -116,8 +290,7 @@ async fn get_blockdata_rpc(
 
     let block_retrieval_start_time = SystemTime::now();
 
-    let rpc_block = eth_client
-        .get_block_by_number(BlockIdentifier::Number(requested_block_number), true)
+    let rpc_block = retry(|| get_block(&client, requested_block_number as usize, true))
         .await
         .wrap_err("Failed to retrieve requested block")?;
 
@@ -139,12 +312,12 @@ async fn get_blockdata_rpc(
 
     let execution_witness_retrieval_start_time = SystemTime::now();
 
-    let witness_rpc = match eth_client
-        .get_witness(BlockIdentifier::Number(requested_block_number), None)
-        .await
-    {
+    let witness_rpc = match get_witness(&client, requested_block_number, None).await {
         Ok(witness) => witness,
-        Err(EthClientError::RequestError(_)) => {
+        Err(e)
+            if e.downcast_ref::<JsonRpcError>()
+                .is_some_and(JsonRpcError::is_method_not_supported) =>
+        {
             warn!("debug_executionWitness endpoint not implemented, using fallback eth_getProof");
 
             #[cfg(feature = "l2")]
@@ -156,16 +329,15 @@ async fn get_blockdata_rpc(
                 "Caching callers and recipients state for block {}",
                 requested_block_number
             );
+            let checkpoint_path =
+                cache_dir.join(get_checkpoint_file_name(&network, requested_block_number));
             let rpc_db = RpcDB::with_cache(
-                eth_client
-                    .urls
-                    .first()
-                    .ok_or_eyre("No RPC URLs configured")?
-                    .as_str(),
+                client.clone(),
                 chain_config,
                 requested_block_number,
                 &block,
                 vm_type,
+                Some(checkpoint_path),
             )
             .await
             .wrap_err("failed to create rpc db")?;
@@ -174,14 +346,23 @@ async fn get_blockdata_rpc(
                 "Pre executing block {}. This may take a while.",
                 requested_block_number
             );
-            let rpc_db = rpc_db
+            let witness = rpc_db
                 .to_execution_witness(&block)
+                .inspect_err(|_| {
+                    // Keep everything fetched so far so that the next run can resume from it.
+                    if let Err(e) = rpc_db.write_checkpoint() {
+                        warn!("Failed to write checkpoint: {e}");
+                    }
+                })
                 .wrap_err("failed to build execution db")?;
             info!(
                 "Finished building execution witness for block {}",
                 requested_block_number
             );
-            rpc_db
+            if let Err(e) = rpc_db.delete_checkpoint() {
+                warn!("Failed to delete the checkpoint of block {requested_block_number}: {e}");
+            }
+            witness
         }
         Err(e) => {
             return Err(eyre::eyre!(format!(
@@ -214,7 +395,7 @@ use ethrex_common::types::{ChainConfig, fee_config::FeeConfig};
 
 #[cfg(feature = "l2")]
 async fn fetch_rangedata_from_client(
-    eth_client: EthClient,
+    client: RpcClient,
     chain_config: ChainConfig,
     from: u64,
     to: u64,
@@ -222,9 +403,9 @@ async fn fetch_rangedata_from_client(
 ) -> eyre::Result<Cache> {
     info!("Validating RPC chain ID");
 
-    let chain_id = eth_client.get_chain_id().await?;
+    let chain_id = get_chain_id(&client).await?;
 
-    if chain_id != chain_config.chain_id.into() {
+    if chain_id != chain_config.chain_id {
         return Err(eyre::eyre!(
             "Rpc endpoint returned a different chain id than the one set by --network"
         ));
@@ -240,8 +421,7 @@ async fn fetch_rangedata_from_client(
     let block_retrieval_start_time = SystemTime::now();
 
     for block_number in from..=to {
-        let rpc_block = eth_client
-            .get_block_by_number(BlockIdentifier::Number(block_number), true)
+        let rpc_block = retry(|| get_block(&client, block_number as usize, true))
             .await
             .wrap_err(format!("failed to fetch block {block_number}"))?;
 
@@ -261,16 +441,11 @@ async fn fetch_rangedata_from_client(
         format_duration(&block_retrieval_duration)
     );
 
-    let from_identifier = BlockIdentifier::Number(from);
-
-    let to_identifier = BlockIdentifier::Number(to);
-
     info!("Getting execution witness from RPC for blocks {from} to {to}");
 
     let execution_witness_retrieval_start_time = SystemTime::now();
 
-    let witness_rpc = eth_client
-        .get_witness(from_identifier, Some(to_identifier))
+    let witness_rpc = get_witness(&client, from, Some(to))
         .await
         .wrap_err("Failed to get execution witness for range")?;
 
@@ -292,12 +467,12 @@ async fn fetch_rangedata_from_client(
 
 #[cfg(feature = "l2")]
 pub async fn get_batchdata(
-    rollup_client: EthClient,
+    rollup_client: RpcClient,
     network: Network,
     batch_number: u64,
     cache_dir: PathBuf,
 ) -> eyre::Result<Cache> {
-    use ethrex_l2_rpc::clients::get_batch_by_number;
+    use crate::rpc::get_batch_by_number;
 
     let file_name = get_batch_cache_file_name(batch_number);
     if let Ok(cache) = Cache::load(&cache_dir, &file_name) {
@@ -2,11 +2,20 @@ mod cache;
 pub mod cli;
 mod fetcher;
 mod helpers;
+mod output_verification;
+mod overrides;
 #[cfg(not(feature = "l2"))]
 mod plot_composition;
 pub mod profiling;
+mod proofs;
+#[cfg(not(feature = "l2"))]
+mod receipts_diff;
 pub mod report;
+mod resource_usage;
 pub mod rpc;
 mod run;
 pub mod slack;
+mod state_diff;
+mod supervisor;
+mod timeout;
 pub mod tx_builder;
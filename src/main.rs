@@ -31,15 +31,17 @@ async fn main() {
             Directive::from_str("ethrex_blockchain::payload=off").expect("this can't fail"),
         );
 
-    tracing::subscriber::set_global_default(
-        tracing_subscriber::FmtSubscriber::builder()
-            .with_env_filter(log_filter)
-            .finish(),
-    )
-    .expect("setting default subscriber failed");
-
     let EthrexReplayCLI { command } = EthrexReplayCLI::parse();
 
+    let subscriber = tracing_subscriber::FmtSubscriber::builder().with_env_filter(log_filter);
+    let result = if command.prints_json() {
+        // Keep stdout for the JSON document, e.g. `--output json`.
+        tracing::subscriber::set_global_default(subscriber.with_writer(std::io::stderr).finish())
+    } else {
+        tracing::subscriber::set_global_default(subscriber.finish())
+    };
+    result.expect("setting default subscriber failed");
+
     if let Err(e) = command.run().await {
         tracing::error!("{e:?}");
         std::process::exit(1);
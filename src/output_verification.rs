@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use ethrex_common::{H256, U256, types::block_execution_witness::GuestProgramState};
+use ethrex_rpc::debug::execution_witness::execution_witness_from_rpc_chain_config;
+use eyre::{OptionExt, WrapErr};
+
+use crate::cache::Cache;
+
+/// Fields of the guest program output that can be checked against the chain, as committed by
+/// a backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestOutput {
+    pub initial_state_hash: H256,
+    pub final_state_hash: H256,
+    pub last_block_hash: H256,
+    pub chain_id: U256,
+}
+
+/// Position of the last block hash in the encoded output, in 32-byte words. The L2 output
+/// commits to the L1 messages, privileged transactions and blob before it.
+#[cfg(not(feature = "l2"))]
+const LAST_BLOCK_HASH_WORD: usize = 2;
+#[cfg(feature = "l2")]
+const LAST_BLOCK_HASH_WORD: usize = 5;
+
+/// Decodes the public values committed by the guest program, encoded by
+/// `ProgramOutput::encode`.
+pub fn decode_program_output(public_values: &[u8]) -> eyre::Result<GuestOutput> {
+    let word = |index: usize| {
+        public_values
+            .get(index * 32..(index + 1) * 32)
+            .ok_or_else(|| {
+                eyre::eyre!(
+                    "Public values are {} bytes long, too short for a guest program output",
+                    public_values.len()
+                )
+            })
+    };
+
+    Ok(GuestOutput {
+        initial_state_hash: H256::from_slice(word(0)?),
+        final_state_hash: H256::from_slice(word(1)?),
+        last_block_hash: H256::from_slice(word(LAST_BLOCK_HASH_WORD)?),
+        chain_id: U256::from_big_endian(word(LAST_BLOCK_HASH_WORD + 1)?),
+    })
+}
+
+/// Value of the guest program output that doesn't match the chain.
+pub struct OutputMismatch {
+    pub field: &'static str,
+    pub output: String,
+    pub expected: String,
+}
+
+impl Display for OutputMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: guest output {}, expected {}",
+            self.field, self.output, self.expected
+        )
+    }
+}
+
+/// Decodes the guest program output committed by a backend and checks it against the cached
+/// headers: the initial state root must be the parent's, the final one the last block's, and
+/// the last block hash and chain id must match. Returns the fields that don't.
+pub fn verify_program_output(
+    cache: &Cache,
+    public_values: &[u8],
+) -> eyre::Result<Vec<OutputMismatch>> {
+    let output = decode_program_output(public_values)?;
+
+    let chain_config = cache.get_chain_config()?;
+    let first_block_number = cache.get_first_block_number()?;
+    let last_block = cache.blocks.last().ok_or_eyre("missing block data")?;
+
+    let execution_witness = execution_witness_from_rpc_chain_config(
+        cache.witness.clone(),
+        chain_config,
+        first_block_number,
+    )
+    .wrap_err("Failed to convert execution witness")?;
+    let guest_program_state: GuestProgramState =
+        execution_witness.try_into().map_err(eyre::Error::msg)?;
+
+    let checks = [
+        (
+            "initial state root",
+            output.initial_state_hash,
+            guest_program_state.parent_block_header.state_root,
+        ),
+        (
+            "final state root",
+            output.final_state_hash,
+            last_block.header.state_root,
+        ),
+        ("last block hash", output.last_block_hash, last_block.hash()),
+    ];
+
+    let mut mismatches: Vec<OutputMismatch> = checks
+        .into_iter()
+        .filter(|(_, output, expected)| output != expected)
+        .map(|(field, output, expected)| OutputMismatch {
+            field,
+            output: format!("{output:#x}"),
+            expected: format!("{expected:#x}"),
+        })
+        .collect();
+
+    if output.chain_id != U256::from(chain_config.chain_id) {
+        mismatches.push(OutputMismatch {
+            field: "chain id",
+            output: output.chain_id.to_string(),
+            expected: chain_config.chain_id.to_string(),
+        });
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethrex_common::{H256, U256};
+    use ethrex_guest_program::output::ProgramOutput;
+
+    use super::{GuestOutput, decode_program_output};
+
+    fn expected_output() -> GuestOutput {
+        GuestOutput {
+            initial_state_hash: H256::repeat_byte(1),
+            final_state_hash: H256::repeat_byte(2),
+            last_block_hash: H256::repeat_byte(3),
+            chain_id: U256::from(560048),
+        }
+    }
+
+    #[cfg(not(feature = "l2"))]
+    #[test]
+    fn decodes_l1_program_output() {
+        let expected = expected_output();
+        let output = ProgramOutput {
+            initial_state_hash: expected.initial_state_hash,
+            final_state_hash: expected.final_state_hash,
+            last_block_hash: expected.last_block_hash,
+            chain_id: expected.chain_id,
+        };
+
+        assert_eq!(decode_program_output(&output.encode()).unwrap(), expected);
+    }
+
+    #[cfg(feature = "l2")]
+    #[test]
+    fn decodes_l2_program_output() {
+        let expected = expected_output();
+        let output = ProgramOutput {
+            initial_state_hash: expected.initial_state_hash,
+            final_state_hash: expected.final_state_hash,
+            l1messages_merkle_root: H256::repeat_byte(4),
+            privileged_transactions_hash: H256::repeat_byte(5),
+            blob_versioned_hash: H256::repeat_byte(6),
+            last_block_hash: expected.last_block_hash,
+            chain_id: expected.chain_id,
+            non_privileged_count: U256::from(7),
+        };
+
+        assert_eq!(decode_program_output(&output.encode()).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_truncated_output() {
+        assert!(decode_program_output(&[0; 64]).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use std::{collections::HashMap, path::Path};
+
+use bytes::Bytes;
+use ethrex_common::{
+    Address, H256, U256,
+    types::{AccountInfo, AccountUpdate, Code, Transaction},
+};
+use ethrex_vm::{GuestProgramStateWrapper, VmDatabase};
+use eyre::WrapErr;
+use serde::Deserialize;
+
+/// Modifications applied to a transaction before replaying it, to see what would have
+/// happened if it had been sent differently.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub tx: TxOverrides,
+    pub state: StateOverride,
+}
+
+/// Transaction fields to replace. The sender is replaced without re-signing, as the
+/// signature isn't checked when replaying.
+#[derive(Clone, Default)]
+pub struct TxOverrides {
+    pub gas_limit: Option<u64>,
+    pub data: Option<Bytes>,
+    pub value: Option<U256>,
+    pub sender: Option<Address>,
+    /// Replaces the gas price of transactions that don't have fee caps.
+    pub max_fee_per_gas: Option<u64>,
+    pub max_priority_fee_per_gas: Option<u64>,
+}
+
+/// State to replace before the transaction, in the format of `eth_call`'s `stateOverride`.
+pub type StateOverride = HashMap<Address, AccountOverride>;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountOverride {
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    /// Hex encoded bytecode.
+    pub code: Option<String>,
+    /// Replaces the whole storage of the account.
+    pub state: Option<HashMap<H256, U256>>,
+    /// Replaces only the given storage slots.
+    pub state_diff: Option<HashMap<H256, U256>>,
+}
+
+impl Overrides {
+    pub fn is_empty(&self) -> bool {
+        let tx = &self.tx;
+        tx.gas_limit.is_none()
+            && tx.data.is_none()
+            && tx.value.is_none()
+            && tx.sender.is_none()
+            && tx.max_fee_per_gas.is_none()
+            && tx.max_priority_fee_per_gas.is_none()
+            && self.state.is_empty()
+    }
+}
+
+impl TxOverrides {
+    pub fn apply(&self, tx: &Transaction, sender: Address) -> (Transaction, Address) {
+        let mut tx = tx.clone();
+
+        macro_rules! override_fields {
+            ($tx:ident, $gas_limit:ident, fee_caps) => {{
+                override_fields!($tx, $gas_limit);
+                if let Some(max_fee_per_gas) = self.max_fee_per_gas {
+                    $tx.max_fee_per_gas = max_fee_per_gas.into();
+                }
+                if let Some(max_priority_fee_per_gas) = self.max_priority_fee_per_gas {
+                    $tx.max_priority_fee_per_gas = max_priority_fee_per_gas.into();
+                }
+            }};
+            ($tx:ident, $gas_limit:ident, gas_price) => {{
+                override_fields!($tx, $gas_limit);
+                if let Some(max_fee_per_gas) = self.max_fee_per_gas {
+                    $tx.gas_price = max_fee_per_gas.into();
+                }
+            }};
+            ($tx:ident, $gas_limit:ident) => {{
+                if let Some(gas_limit) = self.gas_limit {
+                    $tx.$gas_limit = gas_limit;
+                }
+                if let Some(data) = &self.data {
+                    $tx.data = data.clone();
+                }
+                if let Some(value) = self.value {
+                    $tx.value = value;
+                }
+            }};
+        }
+
+        match &mut tx {
+            Transaction::LegacyTransaction(tx) => override_fields!(tx, gas, gas_price),
+            Transaction::EIP2930Transaction(tx) => override_fields!(tx, gas_limit, gas_price),
+            Transaction::EIP1559Transaction(tx) => override_fields!(tx, gas_limit, fee_caps),
+            Transaction::EIP4844Transaction(tx) => override_fields!(tx, gas, fee_caps),
+            Transaction::EIP7702Transaction(tx) => override_fields!(tx, gas_limit, fee_caps),
+            // L2 specific transactions aren't signed by users, there's nothing to try out.
+            _ => {}
+        }
+
+        (tx, self.sender.unwrap_or(sender))
+    }
+}
+
+/// Reads a `stateOverride` JSON object from a file.
+pub fn read_state_override(path: &Path) -> eyre::Result<StateOverride> {
+    let file = std::fs::File::open(path)
+        .wrap_err_with(|| format!("Failed to open state override {}", path.display()))?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .wrap_err_with(|| format!("Invalid state override {}", path.display()))
+}
+
+/// Checks that the state override can be applied to the block's witness, so that it fails
+/// before anything runs.
+///
+/// Only the accounts and storage slots in the witness are known, as they're what the block
+/// touches. Anything else can't be read or updated, so overriding it is rejected.
+pub fn check_state_override(
+    db: &GuestProgramStateWrapper,
+    state_override: &StateOverride,
+) -> eyre::Result<()> {
+    for (address, account_override) in state_override {
+        if let Some(nonce) = account_override.nonce {
+            override_nonce(*address, nonce)?;
+        }
+
+        db.get_account_state(*address).map_err(|_| {
+            eyre::eyre!(
+                "Can't override {address:#x}: the account isn't in the block's witness, only accounts the block touches can be overridden"
+            )
+        })?;
+
+        let slots = account_override
+            .state
+            .iter()
+            .chain(&account_override.state_diff)
+            .flat_map(HashMap::keys);
+        for slot in slots {
+            db.get_storage_slot(*address, *slot).map_err(|_| {
+                eyre::eyre!(
+                    "Can't override storage slot {slot:#x} of {address:#x}: it isn't in the block's witness, only slots the block touches can be overridden"
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn override_nonce(address: Address, nonce: U256) -> eyre::Result<u64> {
+    nonce
+        .try_into()
+        .map_err(|_| eyre::eyre!("Nonce override {nonce} for {address:#x} doesn't fit in 64 bits"))
+}
+
+/// Applies the state override on top of the current state of the database.
+pub fn apply_state_override(
+    db: &mut GuestProgramStateWrapper,
+    state_override: &StateOverride,
+) -> eyre::Result<()> {
+    let mut account_updates = Vec::with_capacity(state_override.len());
+
+    for (address, account_override) in state_override {
+        let current = db.get_account_state(*address)?.unwrap_or_default();
+
+        let code = account_override
+            .code
+            .as_ref()
+            .map(|code| -> eyre::Result<Code> {
+                let bytecode = hex::decode(code.trim_start_matches("0x"))
+                    .wrap_err_with(|| format!("Invalid code override for {address:#x}"))?;
+                Ok(Code::from_bytecode(Bytes::from(bytecode)))
+            })
+            .transpose()?;
+
+        let mut update = AccountUpdate::new(*address);
+        update.info = Some(AccountInfo {
+            code_hash: code.as_ref().map_or(current.code_hash, |code| code.hash),
+            balance: account_override.balance.unwrap_or(current.balance),
+            nonce: account_override
+                .nonce
+                .map_or(Ok(current.nonce), |nonce| override_nonce(*address, nonce))?,
+        });
+        update.code = code;
+        if let Some(state) = &account_override.state {
+            update.removed_storage = true;
+            update.added_storage = state.clone();
+        }
+        if let Some(state_diff) = &account_override.state_diff {
+            update.added_storage.extend(state_diff);
+        }
+
+        account_updates.push(update);
+    }
+
+    db.apply_account_updates(&account_updates)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use ethrex_common::types::{EIP1559Transaction, LegacyTransaction, Transaction, TxKind};
+    use ethrex_common::{Address, H256, U256};
+
+    use super::{AccountOverride, Overrides, TxOverrides};
+    use crate::{
+        cache::Cache,
+        run::{TxSelection, run_txs},
+    };
+
+    fn cache() -> Cache {
+        let caches_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("caches");
+        Cache::load(&caches_dir, "cache_hoodi_1265656.json").unwrap()
+    }
+
+    fn replay_first_tx(state: HashMap<Address, AccountOverride>) -> eyre::Result<()> {
+        let overrides = Overrides {
+            state,
+            ..Default::default()
+        };
+        futures::executor::block_on(run_txs(cache(), &TxSelection::Indices(0..1), &overrides))
+            .map(|_| ())
+    }
+
+    #[test]
+    fn overriding_an_account_outside_the_witness_is_rejected() {
+        let state = HashMap::from([(
+            Address::repeat_byte(0xee),
+            AccountOverride {
+                balance: Some(U256::one()),
+                ..Default::default()
+            },
+        )]);
+
+        let err = replay_first_tx(state).unwrap_err();
+
+        assert!(err.to_string().contains("isn't in the block's witness"));
+    }
+
+    #[test]
+    fn overriding_a_slot_outside_the_witness_is_rejected() {
+        let cache = cache();
+        let contract = cache.blocks[0]
+            .body
+            .transactions
+            .iter()
+            .find_map(|tx| match tx.to() {
+                TxKind::Call(to) if !tx.data().is_empty() => Some(to),
+                _ => None,
+            })
+            .unwrap();
+        let state = HashMap::from([(
+            contract,
+            AccountOverride {
+                state_diff: Some(HashMap::from([(H256::repeat_byte(0xab), U256::one())])),
+                ..Default::default()
+            },
+        )]);
+
+        let err = replay_first_tx(state).unwrap_err();
+
+        assert!(err.to_string().contains("storage slot"));
+    }
+
+    #[test]
+    fn nonce_override_over_64_bits_is_rejected() {
+        let sender = cache().blocks[0]
+            .body
+            .get_transactions_with_sender()
+            .unwrap()[0]
+            .1;
+        let state = HashMap::from([(
+            sender,
+            AccountOverride {
+                nonce: Some(U256::from(u64::MAX) + 1),
+                ..Default::default()
+            },
+        )]);
+
+        let err = replay_first_tx(state).unwrap_err();
+
+        assert!(err.to_string().contains("doesn't fit in 64 bits"));
+    }
+
+    #[test]
+    fn overriding_an_account_in_the_witness_is_accepted() {
+        let sender = cache().blocks[0]
+            .body
+            .get_transactions_with_sender()
+            .unwrap()[0]
+            .1;
+        let state = HashMap::from([(
+            sender,
+            AccountOverride {
+                balance: Some(U256::from(u64::MAX)),
+                ..Default::default()
+            },
+        )]);
+
+        replay_first_tx(state).unwrap();
+    }
+
+    #[test]
+    fn tx_overrides_replace_fields_and_sender() {
+        let overrides = TxOverrides {
+            gas_limit: Some(100_000),
+            value: Some(U256::from(5)),
+            sender: Some(Address::repeat_byte(2)),
+            max_fee_per_gas: Some(30),
+            max_priority_fee_per_gas: Some(2),
+            ..Default::default()
+        };
+        let tx = Transaction::EIP1559Transaction(EIP1559Transaction::default());
+
+        let (tx, sender) = overrides.apply(&tx, Address::repeat_byte(1));
+
+        let Transaction::EIP1559Transaction(tx) = tx else {
+            panic!("transaction type changed");
+        };
+        assert_eq!(tx.gas_limit, 100_000);
+        assert_eq!(tx.value, U256::from(5));
+        assert_eq!(tx.max_fee_per_gas, 30);
+        assert_eq!(tx.max_priority_fee_per_gas, 2);
+        assert_eq!(sender, Address::repeat_byte(2));
+    }
+
+    #[test]
+    fn max_fee_override_replaces_the_gas_price_of_legacy_txs() {
+        let overrides = TxOverrides {
+            max_fee_per_gas: Some(30),
+            ..Default::default()
+        };
+        let tx = Transaction::LegacyTransaction(LegacyTransaction::default());
+
+        let (tx, sender) = overrides.apply(&tx, Address::repeat_byte(1));
+
+        let Transaction::LegacyTransaction(tx) = tx else {
+            panic!("transaction type changed");
+        };
+        assert_eq!(tx.gas_price, 30.into());
+        assert_eq!(sender, Address::repeat_byte(1));
+    }
+}
@@ -1,6 +1,14 @@
 use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
 use std::time::Duration;
 
+use ethrex_common::H256;
+use eyre::{OptionExt, WrapErr};
+
+use crate::run::TxReplay;
+
 pub struct RunStats {
     sorted: Vec<Duration>,
 }
@@ -85,3 +93,370 @@ pub fn print_individual_runs(prep: &[Duration], exec: &[Duration]) {
         );
     }
 }
+
+/// Header of the CSV written with `--stats-out`. Each run is a row numbered from 1, followed by
+/// a row per statistic.
+const STATS_CSV_HEADER: &str =
+    "network,first_block,last_block,git_commit,row,prep_ms,exec_ms,total_ms";
+
+/// Timings of the `--repeat` runs of a block, exported with `--stats-out` so they can be
+/// ingested without scraping the logs.
+pub struct StatsExport {
+    pub network: String,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub git_commit: Option<String>,
+    pub prep: Vec<Duration>,
+    pub exec: Vec<Duration>,
+    /// Whether the first run decoded the witness that the following runs built their store from.
+    pub reuse_storage: bool,
+}
+
+impl StatsExport {
+    fn totals(&self) -> Vec<Duration> {
+        self.prep
+            .iter()
+            .zip(&self.exec)
+            .map(|(p, e)| *p + *e)
+            .collect()
+    }
+
+    /// Statistics of the prep, exec and total timings, over all runs and, when storage was
+    /// reused, over the runs that reused the decoded witness only.
+    fn stats(&self) -> Vec<(&'static str, [RunStats; 3])> {
+        let stats_from = |skip: usize| {
+            [
+                RunStats::new(self.prep[skip..].to_vec()),
+                RunStats::new(self.exec[skip..].to_vec()),
+                RunStats::new(self.totals()[skip..].to_vec()),
+            ]
+        };
+
+        let mut stats = vec![("all", stats_from(0))];
+        if self.reuse_storage && self.prep.len() > 1 {
+            stats.push(("decodeReused", stats_from(1)));
+        }
+        stats
+    }
+
+    /// Appends the timings to `path`: as CSV rows if it has a `.csv` extension, or to the JSON
+    /// array it holds otherwise. Several blocks, or runs at different commits, can then be
+    /// collected in the same file.
+    pub fn append_to(&self, path: &Path) -> eyre::Result<()> {
+        let result = if path.extension().is_some_and(|extension| extension == "csv") {
+            self.append_csv(path)
+        } else {
+            self.append_json(path)
+        };
+        result.wrap_err_with(|| format!("Failed to write stats to {}", path.display()))
+    }
+
+    fn append_csv(&self, path: &Path) -> eyre::Result<()> {
+        let is_new = !std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0);
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        if is_new {
+            writeln!(file, "{STATS_CSV_HEADER}")?;
+        }
+
+        let key = format!(
+            "{},{},{},{}",
+            self.network,
+            self.first_block,
+            self.last_block,
+            self.git_commit.as_deref().unwrap_or_default()
+        );
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+
+        for (i, ((prep, exec), total)) in self
+            .prep
+            .iter()
+            .zip(&self.exec)
+            .zip(self.totals())
+            .enumerate()
+        {
+            writeln!(
+                file,
+                "{key},{},{:.3},{:.3},{:.3}",
+                i + 1,
+                ms(*prep),
+                ms(*exec),
+                ms(total)
+            )?;
+        }
+
+        for (runs, [prep, exec, total]) in self.stats() {
+            let prefix = if runs == "decodeReused" {
+                "decode_reused_"
+            } else {
+                ""
+            };
+            for (name, value) in [
+                ("min", RunStats::min as fn(&RunStats) -> Duration),
+                ("max", RunStats::max),
+                ("mean", RunStats::mean),
+                ("median", RunStats::median),
+                ("p95", |stats: &RunStats| stats.percentile(95.0)),
+                ("p99", |stats: &RunStats| stats.percentile(99.0)),
+            ] {
+                writeln!(
+                    file,
+                    "{key},{prefix}{name},{:.3},{:.3},{:.3}",
+                    ms(value(&prep)),
+                    ms(value(&exec)),
+                    ms(value(&total))
+                )?;
+            }
+            writeln!(
+                file,
+                "{key},{prefix}stddev,{:.3},{:.3},{:.3}",
+                prep.stddev_ms(),
+                exec.stddev_ms(),
+                total.stddev_ms()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn append_json(&self, path: &Path) -> eyre::Result<()> {
+        let mut entries = match std::fs::read_to_string(path) {
+            Ok(contents) if !contents.trim().is_empty() => {
+                serde_json::from_str::<Vec<serde_json::Value>>(&contents)
+                    .wrap_err("existing file isn't a JSON array")?
+            }
+            _ => Vec::new(),
+        };
+        entries.push(self.to_json());
+
+        // Replace the file at once, so it's never left half written.
+        let file_name = path.file_name().ok_or_eyre("invalid stats path")?;
+        let tmp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&entries)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+        let stats_json = |stats: &RunStats| {
+            serde_json::json!({
+                "minMs": ms(stats.min()),
+                "maxMs": ms(stats.max()),
+                "meanMs": ms(stats.mean()),
+                "medianMs": ms(stats.median()),
+                "stddevMs": stats.stddev_ms(),
+                "p95Ms": ms(stats.percentile(95.0)),
+                "p99Ms": ms(stats.percentile(99.0)),
+            })
+        };
+
+        let runs: Vec<_> = self
+            .prep
+            .iter()
+            .zip(&self.exec)
+            .zip(self.totals())
+            .enumerate()
+            .map(|(i, ((prep, exec), total))| {
+                serde_json::json!({
+                    "run": i + 1,
+                    "prepMs": ms(*prep),
+                    "execMs": ms(*exec),
+                    "totalMs": ms(total),
+                })
+            })
+            .collect();
+
+        let stats: serde_json::Map<_, _> = self
+            .stats()
+            .into_iter()
+            .map(|(runs, [prep, exec, total])| {
+                (
+                    runs.to_string(),
+                    serde_json::json!({
+                        "prep": stats_json(&prep),
+                        "exec": stats_json(&exec),
+                        "total": stats_json(&total),
+                    }),
+                )
+            })
+            .collect();
+
+        serde_json::json!({
+            "network": self.network,
+            "firstBlock": self.first_block,
+            "lastBlock": self.last_block,
+            "gitCommit": self.git_commit,
+            "reuseStorage": self.reuse_storage,
+            "runs": runs,
+            "stats": stats,
+        })
+    }
+}
+
+/// Execution profile of a single transaction, replayed alone with LEVM.
+pub struct TxProfile {
+    pub index: usize,
+    pub tx_hash: H256,
+    pub gas_used: u64,
+    pub execution_time: Duration,
+}
+
+impl TxProfile {
+    pub(crate) fn from_replay(tx_replay: &TxReplay) -> Self {
+        Self {
+            index: tx_replay.index,
+            tx_hash: tx_replay.tx_hash,
+            // Each transaction is executed with its own cumulative gas counter.
+            gas_used: tx_replay.receipt.cumulative_gas_used,
+            execution_time: tx_replay.execution_time,
+        }
+    }
+
+    pub fn mgas_per_sec(&self) -> f64 {
+        mgas_per_sec(self.gas_used, self.execution_time)
+    }
+}
+
+fn mgas_per_sec(gas_used: u64, duration: Duration) -> f64 {
+    gas_used as f64 / 1e6 / duration.as_secs_f64().max(f64::EPSILON)
+}
+
+/// Logs the transactions ranked by execution time, with their share of the block's execution
+/// time. Transactions running at less than half the throughput of the whole block are marked
+/// as outliers, as they take longer than their gas accounts for.
+pub fn print_tx_profile(block_number: u64, mut profiles: Vec<TxProfile>) {
+    let total_time: Duration = profiles.iter().map(|p| p.execution_time).sum();
+    let total_gas: u64 = profiles.iter().map(|p| p.gas_used).sum();
+    let block_throughput = mgas_per_sec(total_gas, total_time);
+
+    profiles.sort_by(|a, b| b.execution_time.cmp(&a.execution_time));
+
+    tracing::info!(
+        "=== Transaction Profile of block {block_number} ({} txs, {total_gas} gas in {total_time:.2?}, {block_throughput:.2} Mgas/s) ===",
+        profiles.len()
+    );
+    tracing::info!(
+        "  {:>4}  {:>6}  {:<66}  {:>12}  {:>10}  {:>6}  {:>10}",
+        "Rank",
+        "Index",
+        "Hash",
+        "Gas Used",
+        "Time",
+        "Share",
+        "Mgas/s"
+    );
+    for (rank, profile) in profiles.iter().enumerate() {
+        let share =
+            profile.execution_time.as_secs_f64() / total_time.as_secs_f64().max(f64::EPSILON);
+        let throughput = profile.mgas_per_sec();
+        let outlier = if throughput < block_throughput / 2.0 {
+            " *"
+        } else {
+            ""
+        };
+        tracing::info!(
+            "  {:>4}  {:>6}  {:<66}  {:>12}  {:>10}  {:>5.1}%  {:>10.2}{outlier}",
+            rank + 1,
+            profile.index,
+            format!("{:#x}", profile.tx_hash),
+            profile.gas_used,
+            format!("{:.2?}", profile.execution_time),
+            share * 100.0,
+            throughput
+        );
+    }
+    tracing::info!("  * below half the Mgas/s of the whole block");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{STATS_CSV_HEADER, StatsExport};
+
+    fn export(first_block: u64, reuse_storage: bool) -> StatsExport {
+        StatsExport {
+            network: "hoodi".to_string(),
+            first_block,
+            last_block: first_block,
+            git_commit: Some("abc123".to_string()),
+            prep: [30, 10, 20].map(Duration::from_millis).to_vec(),
+            exec: [3, 1, 2].map(Duration::from_millis).to_vec(),
+            reuse_storage,
+        }
+    }
+
+    fn stats_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}_{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn csv_header_is_written_once_and_rows_appended() {
+        let path = stats_path("stats.csv");
+
+        export(1, false).append_to(&path).unwrap();
+        export(2, true).append_to(&path).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines[0], STATS_CSV_HEADER);
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| **line == STATS_CSV_HEADER)
+                .count(),
+            1
+        );
+        // Three runs and seven statistics, over all runs and, for the second block, over the
+        // runs that reused the decoded witness.
+        assert_eq!(lines.len(), 1 + (3 + 7) + (3 + 7 + 7));
+        assert_eq!(lines[1], "hoodi,1,1,abc123,1,30.000,3.000,33.000");
+        assert!(lines.contains(&"hoodi,1,1,abc123,median,20.000,2.000,22.000"));
+        assert!(lines.contains(&"hoodi,2,2,abc123,decode_reused_max,20.000,2.000,22.000"));
+    }
+
+    #[test]
+    fn json_entries_are_appended_to_the_array() {
+        let path = stats_path("stats.json");
+
+        export(1, false).append_to(&path).unwrap();
+        export(2, true).append_to(&path).unwrap();
+
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["firstBlock"], 1);
+        assert_eq!(entries[0]["runs"].as_array().unwrap().len(), 3);
+        assert!(entries[0]["stats"].get("decodeReused").is_none());
+        assert_eq!(entries[1]["firstBlock"], 2);
+        let reused_min_ms = entries[1]["stats"]["decodeReused"]["total"]["minMs"]
+            .as_f64()
+            .unwrap();
+        assert!((reused_min_ms - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn json_file_that_isnt_an_array_is_left_untouched() {
+        let path = stats_path("not_stats.json");
+        std::fs::write(&path, "{}").unwrap();
+
+        let result = export(1, false).append_to(&path);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(contents, "{}");
+    }
+}
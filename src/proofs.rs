@@ -0,0 +1,258 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use ethrex_common::H256;
+#[cfg(any(
+    feature = "sp1",
+    feature = "risc0",
+    feature = "zisk",
+    feature = "openvm"
+))]
+use ethrex_common::utils::keccak;
+use ethrex_prover::{BackendType, ProverBackend};
+use eyre::{OptionExt, WrapErr};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{cache::Cache, cli::ProofType, run::compiled_backends};
+
+/// A proof generated by `prove`, with what's needed to verify it later.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedProof {
+    /// Name of the backend that generated the proof, as in `BackendType`'s `Debug` output.
+    pub backend: String,
+    pub proof_type: ProofType,
+    /// Identifies the guest program the proof was generated for, see [`guest_program_id`].
+    pub guest_program_id: String,
+    pub network: String,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub last_block_hash: H256,
+    /// Guest program output the proof commits to, encoded the way the guest commits it. `None`
+    /// for backends whose proofs aren't decoded.
+    pub public_values: Option<String>,
+    /// Proof as returned by the backend: the SP1 proof with its public values, the RISC0
+    /// receipt, or the backend's `ProofOutput` for the others.
+    pub proof: serde_json::Value,
+}
+
+impl SavedProof {
+    pub fn new(
+        backend: BackendType,
+        proof_type: ProofType,
+        cache: &Cache,
+        public_values: Option<Vec<u8>>,
+        proof: serde_json::Value,
+    ) -> eyre::Result<Self> {
+        let first_block = cache.blocks.first().ok_or_eyre("missing block data")?;
+        let last_block = cache.blocks.last().ok_or_eyre("missing block data")?;
+
+        Ok(Self {
+            backend: format!("{backend:?}"),
+            proof_type,
+            guest_program_id: guest_program_id(backend),
+            network: cache.network.to_string(),
+            first_block: first_block.header.number,
+            last_block: last_block.header.number,
+            last_block_hash: last_block.hash(),
+            public_values: public_values.map(|values| format!("0x{}", hex::encode(values))),
+            proof,
+        })
+    }
+
+    pub fn file_name(&self) -> String {
+        let blocks = if self.first_block == self.last_block {
+            self.first_block.to_string()
+        } else {
+            format!("{}-{}", self.first_block, self.last_block)
+        };
+        format!(
+            "proof_{}_{blocks}_{}_{}.json",
+            self.network,
+            self.backend.to_lowercase(),
+            self.proof_type.to_string().to_lowercase()
+        )
+    }
+
+    /// Writes the proof to `dir`, returning the path of the file.
+    pub fn save(&self, dir: &Path) -> eyre::Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Failed to create proofs directory {}", dir.display()))?;
+        let path = dir.join(self.file_name());
+        let file =
+            File::create(&path).wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let file =
+            File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .wrap_err_with(|| format!("Invalid proof file {}", path.display()))
+    }
+
+    /// Verifies the proof with the backend that generated it, which has to be compiled in.
+    pub fn verify(&self) -> eyre::Result<BackendType> {
+        let backend = compiled_backends()
+            .into_iter()
+            .find(|backend| format!("{backend:?}") == self.backend)
+            .ok_or_else(|| {
+                eyre::eyre!(
+                    "The proof was generated with {}, which isn't compiled in",
+                    self.backend
+                )
+            })?;
+
+        let guest_program_id = guest_program_id(backend);
+        if self.guest_program_id != guest_program_id {
+            return Err(eyre::eyre!(
+                "The proof was generated for guest program {}, but this binary embeds {guest_program_id}",
+                self.guest_program_id
+            ));
+        }
+
+        match backend {
+            BackendType::Exec => verify_with(ethrex_prover::ExecBackend::new(), &self.proof),
+            #[cfg(feature = "sp1")]
+            BackendType::SP1 => verify_sp1(&self.proof),
+            #[cfg(feature = "risc0")]
+            BackendType::RISC0 => verify_risc0(&self.proof),
+            #[cfg(feature = "zisk")]
+            BackendType::ZisK => verify_with(ethrex_prover::ZiskBackend::new(), &self.proof),
+            #[cfg(feature = "openvm")]
+            BackendType::OpenVM => verify_with(ethrex_prover::OpenVmBackend::new(), &self.proof),
+        }?;
+
+        Ok(backend)
+    }
+}
+
+/// Serializes a proof returned by a backend to store it in a [`SavedProof`].
+pub fn serialize_proof<B: ProverBackend>(proof: &B::ProofOutput) -> eyre::Result<serde_json::Value>
+where
+    B::ProofOutput: Serialize,
+{
+    serde_json::to_value(proof).wrap_err("Failed to serialize proof")
+}
+
+fn verify_with<B: ProverBackend>(backend: B, proof: &serde_json::Value) -> eyre::Result<()>
+where
+    B::ProofOutput: DeserializeOwned,
+{
+    let proof: B::ProofOutput =
+        serde_json::from_value(proof.clone()).wrap_err("Failed to deserialize proof")?;
+    backend
+        .verify(&proof)
+        .map_err(|e| eyre::Error::msg(format!("Verification failed: {e}")))
+}
+
+/// Verifies a proof generated by `run::prove_sp1` against the verifying key of the embedded
+/// guest program.
+#[cfg(feature = "sp1")]
+fn verify_sp1(proof: &serde_json::Value) -> eyre::Result<()> {
+    let proof: sp1_sdk::SP1ProofWithPublicValues =
+        serde_json::from_value(proof.clone()).wrap_err("Failed to deserialize proof")?;
+    let client = crate::run::sp1_client();
+    let (_, verifying_key) = client.setup(ethrex_guest_program::ZKVM_SP1_PROGRAM_ELF);
+    client
+        .verify(&proof, &verifying_key)
+        .map_err(|e| eyre::Error::msg(format!("Verification failed: {e}")))
+}
+
+/// Verifies a receipt generated by `run::prove_risc0` against the image id of the embedded
+/// guest program.
+#[cfg(feature = "risc0")]
+fn verify_risc0(proof: &serde_json::Value) -> eyre::Result<()> {
+    let receipt: risc0_zkvm::Receipt =
+        serde_json::from_value(proof.clone()).wrap_err("Failed to deserialize proof")?;
+    let image_id = risc0_zkvm::compute_image_id(ethrex_guest_program::ZKVM_RISC0_PROGRAM_ELF)
+        .map_err(|e| eyre::Error::msg(format!("Failed to compute image id: {e}")))?;
+    receipt
+        .verify(image_id)
+        .map_err(|e| eyre::Error::msg(format!("Verification failed: {e}")))
+}
+
+/// Identifier of the guest program a backend proves: the keccak hash of its ELF for zkVMs.
+/// `ExecBackend` runs the guest program natively, so there's no program to identify.
+fn guest_program_id(backend: BackendType) -> String {
+    match backend {
+        BackendType::Exec => "native".to_string(),
+        #[cfg(feature = "sp1")]
+        BackendType::SP1 => format!("{:#x}", keccak(ethrex_guest_program::ZKVM_SP1_PROGRAM_ELF)),
+        #[cfg(feature = "risc0")]
+        BackendType::RISC0 => {
+            format!(
+                "{:#x}",
+                keccak(ethrex_guest_program::ZKVM_RISC0_PROGRAM_ELF)
+            )
+        }
+        #[cfg(feature = "zisk")]
+        BackendType::ZisK => format!("{:#x}", keccak(ethrex_guest_program::ZKVM_ZISK_PROGRAM_ELF)),
+        #[cfg(feature = "openvm")]
+        BackendType::OpenVM => {
+            format!(
+                "{:#x}",
+                keccak(ethrex_guest_program::ZKVM_OPENVM_PROGRAM_ELF)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ethrex_prover::BackendType;
+
+    use super::SavedProof;
+    use crate::{
+        cache::Cache, cli::ProofType, output_verification::decode_program_output,
+        resource_usage::UsageRecorder, run::prove,
+    };
+
+    #[test]
+    fn exec_proof_round_trips_through_saved_proof() {
+        let caches_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("caches");
+        let cache = Cache::load(&caches_dir, "cache_hoodi_1265656.json").unwrap();
+        let last_block_hash = cache.blocks.last().unwrap().hash();
+        let proofs_dir =
+            std::env::temp_dir().join(format!("ethrex_replay_proofs_{}", std::process::id()));
+
+        let proving = futures::executor::block_on(prove(
+            BackendType::Exec,
+            ProofType::Compressed,
+            cache,
+            proofs_dir.clone(),
+            UsageRecorder::default(),
+        ))
+        .unwrap();
+
+        let path = std::fs::read_dir(&proofs_dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let saved_proof = SavedProof::load(&path).unwrap();
+        std::fs::remove_dir_all(&proofs_dir).unwrap();
+
+        assert!(matches!(saved_proof.verify().unwrap(), BackendType::Exec));
+        assert_eq!(saved_proof.last_block_hash, last_block_hash);
+
+        let public_values = proving.public_values.unwrap();
+        assert_eq!(
+            saved_proof.public_values,
+            Some(format!("0x{}", hex::encode(&public_values)))
+        );
+        assert_eq!(
+            decode_program_output(&public_values)
+                .unwrap()
+                .last_block_hash,
+            last_block_hash
+        );
+    }
+}
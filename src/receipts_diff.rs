@@ -0,0 +1,220 @@
+use std::{fmt::Display, sync::Arc};
+
+use ethrex_common::{
+    H256,
+    tracing::CallTrace,
+    types::{AccountUpdate, Log, Receipt, bloom_from_logs},
+};
+use ethrex_levm::{db::gen_db::GeneralizedDatabase, vm::VMType};
+use ethrex_vm::{DynVmDatabase, Evm, backends::levm::LEVM};
+use eyre::OptionExt;
+use tracing::warn;
+
+use crate::{
+    cache::Cache,
+    rpc::{RpcLog, RpcReceipt},
+    state_diff::witness_db,
+};
+
+/// First transaction whose LEVM receipt differs from the canonical one.
+pub struct ReceiptDivergence {
+    pub tx_index: usize,
+    pub tx_hash: H256,
+    pub fields: Vec<ReceiptFieldDiff>,
+    /// Call trace of the transaction, executed on top of the same state.
+    pub trace: Option<CallTrace>,
+    /// Account updates produced by the transaction.
+    pub transitions: Vec<AccountUpdate>,
+}
+
+pub struct ReceiptFieldDiff {
+    pub field: String,
+    pub computed: String,
+    pub canonical: String,
+}
+
+impl Display for ReceiptFieldDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: computed {}, canonical {}",
+            self.field, self.computed, self.canonical
+        )
+    }
+}
+
+/// Executes the transactions of the block one by one, like `run_txs` does, and compares each
+/// receipt with the canonical one. Stops at the first divergent transaction.
+pub fn first_divergent_receipt(
+    cache: Cache,
+    canonical_receipts: &[RpcReceipt],
+) -> eyre::Result<Option<ReceiptDivergence>> {
+    let chain_config = cache.get_chain_config()?;
+    let block = cache
+        .blocks
+        .first()
+        .ok_or_eyre("missing block data")?
+        .clone();
+
+    if canonical_receipts.len() != block.body.transactions.len() {
+        return Err(eyre::eyre!(
+            "RPC returned {} receipts for a block with {} transactions",
+            canonical_receipts.len(),
+            block.body.transactions.len()
+        ));
+    }
+
+    let mut wrapped_db = witness_db(cache.witness, chain_config, block.header.number)?;
+
+    let changes = {
+        let store: Arc<DynVmDatabase> = Arc::new(Box::new(wrapped_db.clone()));
+        let mut db = GeneralizedDatabase::new(store.clone());
+        LEVM::prepare_block(&block, &mut db, VMType::L1)?;
+        LEVM::get_state_transitions(&mut db)?
+    };
+    wrapped_db.apply_account_updates(&changes)?;
+
+    let mut remaining_gas = block.header.gas_limit;
+    let mut cumulative_gas_spent = 0;
+
+    for (tx_index, ((tx, tx_sender), canonical)) in block
+        .body
+        .get_transactions_with_sender()?
+        .into_iter()
+        .zip(canonical_receipts)
+        .enumerate()
+    {
+        let mut vm = Evm::new_for_l1(wrapped_db.clone());
+
+        let execution = vm.execute_tx(
+            tx,
+            &block.header,
+            &mut remaining_gas,
+            &mut cumulative_gas_spent,
+            tx_sender,
+        );
+
+        let (fields, transitions) = match execution {
+            Ok((receipt, _)) => (
+                diff_receipt(&receipt, canonical)?,
+                vm.get_state_transitions()?,
+            ),
+            Err(e) => (
+                vec![ReceiptFieldDiff {
+                    field: "execution".to_string(),
+                    computed: format!("failed with {e}"),
+                    canonical: "included".to_string(),
+                }],
+                Vec::new(),
+            ),
+        };
+
+        if !fields.is_empty() {
+            // The trace is taken on top of the state before the transaction.
+            let mut tracer = Evm::new_for_l1(wrapped_db.clone());
+
+            let trace = tracer
+                .trace_tx_calls(&block, tx_index, false, true)
+                .inspect_err(|e| warn!("Failed to trace transaction {tx_index}: {e}"))
+                .ok();
+
+            return Ok(Some(ReceiptDivergence {
+                tx_index,
+                tx_hash: tx.hash(),
+                fields,
+                trace,
+                transitions,
+            }));
+        }
+
+        wrapped_db.apply_account_updates(&transitions)?;
+    }
+
+    Ok(None)
+}
+
+fn diff_receipt(receipt: &Receipt, canonical: &RpcReceipt) -> eyre::Result<Vec<ReceiptFieldDiff>> {
+    let mut fields = Vec::new();
+
+    if let Some(status) = canonical.status {
+        let canonical_succeeded = !status.is_zero();
+        if receipt.succeeded != canonical_succeeded {
+            fields.push(ReceiptFieldDiff {
+                field: "status".to_string(),
+                computed: status_str(receipt.succeeded).to_string(),
+                canonical: status_str(canonical_succeeded).to_string(),
+            });
+        }
+    }
+
+    if receipt.cumulative_gas_used != canonical.cumulative_gas_used.as_u64() {
+        fields.push(ReceiptFieldDiff {
+            field: "cumulative gas used".to_string(),
+            computed: receipt.cumulative_gas_used.to_string(),
+            canonical: canonical.cumulative_gas_used.to_string(),
+        });
+    }
+
+    if receipt.logs.len() != canonical.logs.len() {
+        fields.push(ReceiptFieldDiff {
+            field: "logs".to_string(),
+            computed: format!("{} logs", receipt.logs.len()),
+            canonical: format!("{} logs", canonical.logs.len()),
+        });
+    } else {
+        for (i, (log, canonical_log)) in receipt.logs.iter().zip(&canonical.logs).enumerate() {
+            if !log_matches(log, canonical_log)? {
+                fields.push(ReceiptFieldDiff {
+                    field: format!("logs[{i}]"),
+                    computed: format_log(log),
+                    canonical: format!(
+                        "{:#x} ({}) => {}",
+                        canonical_log.address,
+                        format_topics(&canonical_log.topics),
+                        canonical_log.data
+                    ),
+                });
+                // Later logs are usually just as wrong, the first one is enough to debug.
+                break;
+            }
+        }
+    }
+
+    let bloom = bloom_from_logs(&receipt.logs);
+    if bloom != canonical.logs_bloom {
+        fields.push(ReceiptFieldDiff {
+            field: "logs bloom".to_string(),
+            computed: format!("{bloom:#x}"),
+            canonical: format!("{:#x}", canonical.logs_bloom),
+        });
+    }
+
+    Ok(fields)
+}
+
+fn log_matches(log: &Log, canonical: &RpcLog) -> eyre::Result<bool> {
+    Ok(log.address == canonical.address
+        && log.topics == canonical.topics
+        && log.data == canonical.data()?)
+}
+
+fn format_log(log: &Log) -> String {
+    format!(
+        "{:#x} ({}) => 0x{}",
+        log.address,
+        format_topics(&log.topics),
+        hex::encode(&log.data)
+    )
+}
+
+fn format_topics(topics: &[H256]) -> String {
+    topics
+        .iter()
+        .map(|topic| format!("{topic:#x}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn status_str(succeeded: bool) -> &'static str {
+    if succeeded { "success" } else { "failure" }
+}
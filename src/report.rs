@@ -6,7 +6,12 @@ use tracing::{error, info};
 
 use crate::{
     cli::{Action, Resource, ZKVM},
+    output_verification::OutputMismatch,
+    resource_usage::{PhaseUsage, ResourceUsage},
+    run::CycleCount,
     slack::{SlackWebHookActionElement, SlackWebHookBlock, SlackWebHookRequest},
+    state_diff::StateDiff,
+    timeout::is_timeout,
 };
 
 pub struct Report {
@@ -17,6 +22,20 @@ pub struct Report {
     pub network: Network,
     pub execution_result: Option<Result<Duration, eyre::Report>>,
     pub proving_result: Option<Result<Duration, eyre::Report>>,
+    /// Accounts that differ from the canonical post-state, computed with `--state-diff`
+    /// when execution fails.
+    pub(crate) state_diff: Option<StateDiff>,
+    /// Fields of the guest program output that don't match the cached headers, checked once
+    /// the block was executed or proven.
+    pub(crate) output_verification: Option<Result<Vec<OutputMismatch>, eyre::Report>>,
+    /// Cycles the zkVM reported while executing, if it reports them.
+    pub(crate) cycles: Option<CycleCount>,
+    /// Gas used and transactions of all the blocks the cycles were counted over, which may be
+    /// more than `block` with multi-block caches.
+    cycles_gas_used: u64,
+    cycles_txs: usize,
+    /// Peak memory and CPU time of each phase of the run.
+    pub(crate) resource_usage: PhaseUsage,
 }
 
 impl Report {
@@ -29,6 +48,8 @@ impl Report {
         execution_result: Option<Result<Duration, eyre::Report>>,
         proving_result: Option<Result<Duration, eyre::Report>>,
     ) -> Self {
+        let cycles_gas_used = block.header.gas_used;
+        let cycles_txs = block.body.transactions.len();
         Self {
             zkvm,
             resource,
@@ -37,11 +58,110 @@ impl Report {
             network,
             execution_result,
             proving_result,
+            state_diff: None,
+            output_verification: None,
+            cycles: None,
+            cycles_gas_used,
+            cycles_txs,
+            resource_usage: PhaseUsage::default(),
         }
     }
 
+    /// Sets the cycles the zkVM reported for executing `blocks` at once.
+    pub(crate) fn set_cycles(&mut self, cycles: Option<CycleCount>, blocks: &[Block]) {
+        self.cycles = cycles;
+        self.cycles_gas_used = blocks.iter().map(|block| block.header.gas_used).sum();
+        self.cycles_txs = blocks
+            .iter()
+            .map(|block| block.body.transactions.len())
+            .sum();
+    }
+
     pub fn has_error(&self) -> bool {
-        matches!(self.execution_result, Some(Err(_))) || matches!(self.proving_result, Some(Err(_)))
+        matches!(self.execution_result, Some(Err(_)))
+            || matches!(self.proving_result, Some(Err(_)))
+            || self.output_verification_failed()
+    }
+
+    pub fn cycles_per_gas(&self) -> Option<f64> {
+        let cycles = self.cycles?.cycles;
+        let gas = self.cycles_gas_used;
+        (gas > 0).then(|| cycles as f64 / gas as f64)
+    }
+
+    pub fn cycles_per_tx(&self) -> Option<f64> {
+        let cycles = self.cycles?.cycles;
+        let txs = self.cycles_txs;
+        (txs > 0).then(|| cycles as f64 / txs as f64)
+    }
+
+    fn cycles_summary(&self) -> Option<String> {
+        let cycle_count = self.cycles?;
+        let mut summary = cycle_count.cycles.to_string();
+        if let Some(segments) = cycle_count.segments {
+            summary.push_str(&format!(" ({segments} segments)"));
+        }
+        if let Some(cycles_per_gas) = self.cycles_per_gas() {
+            summary.push_str(&format!(", {cycles_per_gas:.2} cycles/gas"));
+        }
+        if let Some(cycles_per_tx) = self.cycles_per_tx() {
+            summary.push_str(&format!(", {cycles_per_tx:.0} cycles/tx"));
+        }
+        Some(summary)
+    }
+
+    fn phases_summary(&self) -> Option<String> {
+        let phases = self.resource_usage.phases();
+        if phases.is_empty() {
+            return None;
+        }
+        Some(
+            phases
+                .into_iter()
+                .map(|(phase, usage)| format!("{phase} ({})", format_resource_usage(&usage)))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+
+    /// Peak RSS of the phase the benchmark is about, proving if the block was proven.
+    fn bench_peak_rss(&self) -> Option<u64> {
+        self.resource_usage
+            .proving
+            .or(self.resource_usage.execution)?
+            .peak_rss
+    }
+
+    /// Whether fetching, execution or proving was given up on after its timeout.
+    pub fn timed_out(&self) -> bool {
+        [&self.execution_result, &self.proving_result]
+            .into_iter()
+            .any(|result| matches!(result, Some(Err(err)) if is_timeout(err)))
+    }
+
+    fn output_verification_failed(&self) -> bool {
+        match &self.output_verification {
+            Some(Ok(mismatches)) => !mismatches.is_empty(),
+            Some(Err(_)) => true,
+            None => false,
+        }
+    }
+
+    fn output_verification_summary(&self) -> Option<String> {
+        match self.output_verification.as_ref()? {
+            Ok(mismatches) if mismatches.is_empty() => {
+                Some("Guest output matches the block headers".to_string())
+            }
+            Ok(mismatches) => Some(format!(
+                "⚠️ Guest output doesn't match the block headers ({})",
+                mismatches
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )),
+            Err(err) => Some(format!("⚠️ Failed with {err}")),
+        }
     }
 
     pub fn to_slack_message(&self) -> SlackWebHookRequest {
@@ -86,7 +206,12 @@ impl Report {
             blocks: vec![
                 SlackWebHookBlock::Header {
                     text: Box::new(SlackWebHookBlock::PlainText {
-                        text: if self.has_error() {
+                        text: if self.timed_out() {
+                            format!(
+                                "⏱️ Timed out trying to {} Block{} on {}",
+                                self.action, maybe_zkvm, self.resource
+                            )
+                        } else if self.has_error() {
                             format!(
                                 "⚠️ Failed to {} Block{} on {}",
                                 self.action, maybe_zkvm, self.resource
@@ -103,11 +228,23 @@ impl Report {
                 SlackWebHookBlock::Section {
                     text: Box::new(SlackWebHookBlock::Markdown {
                         text: format!(
-                            "*Network:* `{network}`\n*Block:* {number}\n*Gas:* {gas}\n*#Txs:* {txs}{maybe_execution_result}{maybe_proving_result}{maybe_gpu}{maybe_cpu}{maybe_ram}{maybe_git_info}{maybe_execution_time}{maybe_proving_time}",
+                            "*Network:* `{network}`\n*Block:* {number}\n*Gas:* {gas}\n*#Txs:* {txs}{maybe_execution_result}{maybe_state_diff}{maybe_proving_result}{maybe_output_verification}{maybe_gpu}{maybe_cpu}{maybe_ram}{maybe_git_info}{maybe_execution_time}{maybe_cycles}{maybe_proving_time}{maybe_phases}",
                             network = self.network,
                             number = self.block.header.number,
                             gas = self.block.header.gas_used,
                             txs = self.block.body.transactions.len(),
+                            maybe_state_diff = match &self.state_diff {
+                                Some(state_diff) => format!(
+                                    "\n*State Diff:* {} of {} compared accounts differ",
+                                    state_diff.accounts.len(),
+                                    state_diff.compared_accounts
+                                ),
+                                None => "".to_string(),
+                            },
+                            maybe_output_verification = match self.output_verification_summary() {
+                                Some(summary) => format!("\n*Output Verification:* {summary}"),
+                                None => "".to_string(),
+                            },
                             maybe_proving_result = if let Some(Err(err)) = &self.proving_result {
                                 format!("\n*Proving Error:* {err}")
                             } else {
@@ -140,6 +277,10 @@ impl Report {
                                 } else {
                                     "".to_string()
                                 },
+                            maybe_cycles = match self.cycles_summary() {
+                                Some(summary) => format!("\n*Cycles:* {summary}"),
+                                None => "".to_string(),
+                            },
                             maybe_proving_time = if let Some(Ok(proving_duration)) =
                                 &self.proving_result
                             {
@@ -147,6 +288,10 @@ impl Report {
                             } else {
                                 "".to_string()
                             },
+                            maybe_phases = match self.phases_summary() {
+                                Some(summary) => format!("\n*Phases:* {summary}"),
+                                None => "".to_string(),
+                            },
                         ),
                     }),
                 },
@@ -172,12 +317,22 @@ impl Report {
             "".to_string()
         };
 
+        let maybe_cycles = match self.cycles_summary() {
+            Some(summary) => format!(", Cycles: {summary}"),
+            None => "".to_string(),
+        };
+
         let maybe_proving_time = if let Some(Ok(proving_duration)) = &self.proving_result {
             format!(", Proving Time: {}", format_duration(proving_duration))
         } else {
             "".to_string()
         };
 
+        let maybe_phases = match self.phases_summary() {
+            Some(summary) => format!(", Phases: {summary}"),
+            None => "".to_string(),
+        };
+
         let maybe_etherscan_url =
             if let Some(url) = etherscan_url(&self.network, self.block.header.number) {
                 format!(" | {url}")
@@ -207,17 +362,71 @@ impl Report {
         match (self.execution_result.as_ref(), self.proving_result.as_ref()) {
             _ if self.has_error() => {
                 error!(
-                    "[{network}] Block: {block_number}, Gas: {gas}, #Txs: {txs}{maybe_execution_result}{maybe_proving_result}{maybe_etherscan_url}{maybe_ethproofs_url}"
+                    "[{network}] Block: {block_number}, Gas: {gas}, #Txs: {txs}{maybe_execution_result}{maybe_proving_result}{maybe_phases}{maybe_etherscan_url}{maybe_ethproofs_url}"
                 );
+                if let Some(state_diff) = &self.state_diff {
+                    error!("[{network}] Block: {block_number}, {state_diff}");
+                }
+                if self.output_verification_failed()
+                    && let Some(summary) = self.output_verification_summary()
+                {
+                    error!("[{network}] Block: {block_number}, Output Verification: {summary}");
+                }
             }
             _ => {
                 info!(
-                    "[{network}] Block: {block_number}, Gas: {gas}, #Txs: {txs}{maybe_execution_time}{maybe_proving_time}{maybe_etherscan_url}{maybe_ethproofs_url}"
+                    "[{network}] Block: {block_number}, Gas: {gas}, #Txs: {txs}{maybe_execution_time}{maybe_cycles}{maybe_proving_time}{maybe_phases}{maybe_etherscan_url}{maybe_ethproofs_url}"
                 );
             }
         }
     }
 
+    /// JSON document describing the report, for `--report-format json`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let result_json = |result: &Option<Result<Duration, eyre::Report>>| match result {
+            Some(Ok(duration)) => serde_json::json!({
+                "status": "success",
+                "timeMs": duration.as_secs_f64() * 1e3,
+            }),
+            Some(Err(err)) => serde_json::json!({
+                "status": if is_timeout(err) { "timeout" } else { "failure" },
+                "error": err.to_string(),
+            }),
+            None => serde_json::Value::Null,
+        };
+
+        serde_json::json!({
+            "network": self.network.to_string(),
+            "block": self.block.header.number,
+            "gasUsed": self.block.header.gas_used,
+            "txs": self.block.body.transactions.len(),
+            "zkvm": self.zkvm.as_ref().map(ToString::to_string),
+            "resource": self.resource.to_string(),
+            "action": self.action.to_string(),
+            "execution": result_json(&self.execution_result),
+            "proving": result_json(&self.proving_result),
+            "cycles": self.cycles.map(|cycle_count| serde_json::json!({
+                "cycles": cycle_count.cycles,
+                "segments": cycle_count.segments,
+                "cyclesPerGas": self.cycles_per_gas(),
+                "cyclesPerTx": self.cycles_per_tx(),
+            })),
+            "outputVerification": self.output_verification_summary(),
+            "phases": self
+                .resource_usage
+                .phases()
+                .into_iter()
+                .map(|(phase, usage)| serde_json::json!({
+                    "phase": phase,
+                    "timeMs": usage.elapsed.as_secs_f64() * 1e3,
+                    "peakRssBytes": usage.peak_rss,
+                    "cpuTimeMs": usage.cpu_time.map(|cpu_time| cpu_time.as_secs_f64() * 1e3),
+                    "peakGpuMemoryBytes": usage.peak_gpu_memory,
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
     /// Convert the report to a benchmark file in JSON format.
     ///
     /// # CAUTION
@@ -245,15 +454,41 @@ impl Report {
                 )));
             }
         };
-        let json = serde_json::json!([{
-            "name": format!("{}, {}", self.zkvm.as_ref().ok_or_else(|| eyre::Error::msg("--zkvm must be set in CI mode"))?, match self.resource {
-                Resource::CPU => cpu_info().unwrap_or_else(|| "CPU".to_string()),
-                Resource::GPU => gpu_info().unwrap_or_else(|| "GPU".to_string()),
-            }),
+        let zkvm = self
+            .zkvm
+            .as_ref()
+            .ok_or_else(|| eyre::Error::msg("--zkvm must be set in CI mode"))?;
+        let hardware = match self.resource {
+            Resource::CPU => cpu_info().unwrap_or_else(|| "CPU".to_string()),
+            Resource::GPU => gpu_info().unwrap_or_else(|| "GPU".to_string()),
+        };
+        let mut json = serde_json::json!([{
+            "name": format!("{zkvm}, {hardware}"),
             "unit": "Mgas/s",
             "value": self.block.header.gas_used as f64 / 1e6 / elapsed,
         }]);
 
+        if let Some(peak_rss) = self.bench_peak_rss()
+            && let Some(entries) = json.as_array_mut()
+        {
+            entries.push(serde_json::json!({
+                "name": format!("{zkvm}, {hardware}, peak memory"),
+                "unit": "MiB",
+                "value": peak_rss as f64 / MIB,
+            }));
+        }
+
+        // Cycles don't depend on the hardware, so they're tracked per zkVM only.
+        if let Some(cycles_per_gas) = self.cycles_per_gas()
+            && let Some(entries) = json.as_array_mut()
+        {
+            entries.push(serde_json::json!({
+                "name": format!("{zkvm}, cycles per gas"),
+                "unit": "cycles/gas",
+                "value": cycles_per_gas,
+            }));
+        }
+
         Ok(json)
     }
 }
@@ -266,6 +501,11 @@ impl Display for Report {
             "".to_string()
         };
         match (&self.execution_result, &self.proving_result) {
+            _ if self.timed_out() => writeln!(
+                f,
+                "⏱️ Timed out trying to {} Block{} on {}",
+                self.action, maybe_zkvm, self.resource
+            )?,
             _ if self.has_error() => writeln!(
                 f,
                 "⚠️ Failed to {} Block{} on {}",
@@ -294,9 +534,15 @@ impl Display for Report {
         } else if let Some(Err(err)) = &self.execution_result {
             writeln!(f, "Execution Error: {err}")?;
         }
+        if let Some(state_diff) = &self.state_diff {
+            write!(f, "{state_diff}")?;
+        }
         if let Some(Err(err)) = &self.proving_result {
             writeln!(f, "Proving Error: {err}")?;
         }
+        if let Some(summary) = self.output_verification_summary() {
+            writeln!(f, "Output Verification: {summary}")?;
+        }
 
         if let Some(info) = gpu_info() {
             writeln!(f, "GPU: {info}")?;
@@ -320,9 +566,19 @@ impl Display for Report {
         if let Some(Ok(execution_duration)) = &self.execution_result {
             writeln!(f, "Execution Time: {}", format_duration(execution_duration))?;
         }
+        if let Some(summary) = self.cycles_summary() {
+            writeln!(f, "Cycles: {summary}")?;
+        }
         if let Some(Ok(proving_duration)) = &self.proving_result {
             writeln!(f, "Proving Time: {}", format_duration(proving_duration))?;
         }
+        let phases = self.resource_usage.phases();
+        if !phases.is_empty() {
+            writeln!(f, "Phases:")?;
+            for (phase, usage) in phases {
+                writeln!(f, "  {phase}: {}", format_resource_usage(&usage))?;
+            }
+        }
         if let Some(url) = etherscan_url(&self.network, self.block.header.number) {
             writeln!(f, "Etherscan: {url}")?;
         }
@@ -333,6 +589,31 @@ impl Display for Report {
     }
 }
 
+const MIB: f64 = 1024.0 * 1024.0;
+
+fn format_bytes(bytes: u64) -> String {
+    let mib = bytes as f64 / MIB;
+    if mib >= 1024.0 {
+        format!("{:.2} GiB", mib / 1024.0)
+    } else {
+        format!("{mib:.1} MiB")
+    }
+}
+
+fn format_resource_usage(usage: &ResourceUsage) -> String {
+    let mut parts = vec![format_duration(&usage.elapsed)];
+    if let Some(peak_rss) = usage.peak_rss {
+        parts.push(format!("peak RSS {}", format_bytes(peak_rss)));
+    }
+    if let Some(cpu_time) = &usage.cpu_time {
+        parts.push(format!("CPU time {}", format_duration(cpu_time)));
+    }
+    if let Some(peak_gpu_memory) = usage.peak_gpu_memory {
+        parts.push(format!("peak GPU memory {}", format_bytes(peak_gpu_memory)));
+    }
+    parts.join(", ")
+}
+
 fn format_duration(duration: &Duration) -> String {
     let total_seconds = duration.as_secs();
     let hours = total_seconds / 3600;
@@ -468,7 +749,7 @@ fn get_current_git_branch() -> Option<String> {
     }
 }
 
-fn get_current_git_commit() -> Option<String> {
+pub(crate) fn get_current_git_commit() -> Option<String> {
     let output = Command::new("git")
         .args(["rev-parse", "--short", "HEAD"])
         .output()
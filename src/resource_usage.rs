@@ -0,0 +1,276 @@
+use std::{
+    future::Future,
+    process::Command,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Set when the process runs several blocks at once, so that its peak RSS and CPU time no
+/// longer belong to a single block.
+static CONCURRENT_BLOCKS: AtomicBool = AtomicBool::new(false);
+
+/// Stops measuring peak RSS and CPU time for the rest of the process, as blocks run
+/// concurrently would be charged for each other's usage. They're reported as unavailable.
+#[cfg(not(feature = "l2"))]
+pub fn disable_process_usage() {
+    CONCURRENT_BLOCKS.store(true, Ordering::Relaxed);
+}
+
+/// How often `nvidia-smi` is queried while a phase runs.
+const GPU_SAMPLING_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time and resources the process consumed while running a phase.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Wall-clock time the phase took.
+    pub elapsed: Duration,
+    /// Peak resident set size of the process, in bytes. Only measured on Linux.
+    pub peak_rss: Option<u64>,
+    /// User and system CPU time, summed over all the threads of the process.
+    pub cpu_time: Option<Duration>,
+    /// Peak memory in use on the GPUs, in bytes, when running on GPU and `nvidia-smi` is
+    /// available. It covers every process on the GPUs, so provers running in a container
+    /// are accounted for too.
+    pub peak_gpu_memory: Option<u64>,
+}
+
+/// Phases of a block run whose time and resource usage are measured.
+#[derive(Clone, Copy, Debug)]
+pub enum UsagePhase {
+    Fetch,
+    /// Building the program input from the cached witness.
+    Input,
+    /// Serializing the program input into the format the zkVM reads.
+    #[cfg_attr(not(any(feature = "sp1", feature = "risc0")), allow(dead_code))]
+    Serialization,
+    /// Setting up the zkVM client or executor.
+    #[cfg_attr(not(any(feature = "sp1", feature = "risc0")), allow(dead_code))]
+    Setup,
+    /// Generating the proving and verifying keys of the guest program.
+    #[cfg_attr(not(feature = "sp1"), allow(dead_code))]
+    KeySetup,
+    Execution,
+    Proving,
+}
+
+/// Time and resource usage of each phase of a block run, for the phases that ran to
+/// completion.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct PhaseUsage {
+    pub fetch: Option<ResourceUsage>,
+    pub input: Option<ResourceUsage>,
+    pub serialization: Option<ResourceUsage>,
+    pub setup: Option<ResourceUsage>,
+    pub key_setup: Option<ResourceUsage>,
+    pub execution: Option<ResourceUsage>,
+    pub proving: Option<ResourceUsage>,
+}
+
+impl PhaseUsage {
+    /// Measured phases, in the order they run.
+    pub fn phases(&self) -> Vec<(&'static str, ResourceUsage)> {
+        [
+            ("Fetch", self.fetch),
+            ("Input", self.input),
+            ("Serialization", self.serialization),
+            ("Setup", self.setup),
+            ("Key setup", self.key_setup),
+            ("Execution", self.execution),
+            ("Proving", self.proving),
+        ]
+        .into_iter()
+        .filter_map(|(name, usage)| usage.map(|usage| (name, usage)))
+        .collect()
+    }
+
+    fn phase_mut(&mut self, phase: UsagePhase) -> &mut Option<ResourceUsage> {
+        match phase {
+            UsagePhase::Fetch => &mut self.fetch,
+            UsagePhase::Input => &mut self.input,
+            UsagePhase::Serialization => &mut self.serialization,
+            UsagePhase::Setup => &mut self.setup,
+            UsagePhase::KeySetup => &mut self.key_setup,
+            UsagePhase::Execution => &mut self.execution,
+            UsagePhase::Proving => &mut self.proving,
+        }
+    }
+}
+
+/// Records the time and resource usage of the phases of a block run. Cloning it is cheap, so
+/// it can be moved into the blocking tasks that execute and prove.
+///
+/// Peak RSS is reset at the start of every phase, which assumes phases don't overlap.
+#[derive(Clone, Default)]
+pub struct UsageRecorder {
+    usage: Arc<Mutex<PhaseUsage>>,
+    track_gpu: bool,
+}
+
+impl UsageRecorder {
+    pub fn new(track_gpu: bool) -> Self {
+        Self {
+            usage: Arc::default(),
+            track_gpu,
+        }
+    }
+
+    pub fn measure<T>(&self, phase: UsagePhase, f: impl FnOnce() -> T) -> T {
+        let meter = Meter::start(self.track_gpu);
+        let output = f();
+        self.record(phase, meter.stop());
+        output
+    }
+
+    /// Like [`measure`](Self::measure), but keeps the usage of the phase if it was already
+    /// measured, e.g. when the input built for execution is built again for proving.
+    pub fn measure_once<T>(&self, phase: UsagePhase, f: impl FnOnce() -> T) -> T {
+        let measured = self
+            .usage
+            .lock()
+            .is_ok_and(|mut phases| phases.phase_mut(phase).is_some());
+        if measured {
+            f()
+        } else {
+            self.measure(phase, f)
+        }
+    }
+
+    pub async fn measure_async<T>(&self, phase: UsagePhase, fut: impl Future<Output = T>) -> T {
+        let meter = Meter::start(self.track_gpu);
+        let output = fut.await;
+        self.record(phase, meter.stop());
+        output
+    }
+
+    /// Usage of the phases measured so far.
+    pub fn usage(&self) -> PhaseUsage {
+        self.usage.lock().map(|usage| *usage).unwrap_or_default()
+    }
+
+    fn record(&self, phase: UsagePhase, usage: ResourceUsage) {
+        if let Ok(mut phases) = self.usage.lock() {
+            *phases.phase_mut(phase) = Some(usage);
+        }
+    }
+}
+
+struct Meter {
+    start: Instant,
+    rss_reset: bool,
+    cpu_time: Option<Duration>,
+    gpu_sampler: Option<GpuSampler>,
+}
+
+impl Meter {
+    fn start(track_gpu: bool) -> Self {
+        let process_usage = !CONCURRENT_BLOCKS.load(Ordering::Relaxed);
+        Self {
+            start: Instant::now(),
+            rss_reset: process_usage && reset_peak_rss(),
+            cpu_time: process_usage.then(cpu_time).flatten(),
+            gpu_sampler: track_gpu.then(GpuSampler::start),
+        }
+    }
+
+    fn stop(self) -> ResourceUsage {
+        ResourceUsage {
+            elapsed: self.start.elapsed(),
+            // Without a reset, the peak would be the one of the whole process so far.
+            peak_rss: self.rss_reset.then(peak_rss).flatten(),
+            cpu_time: self
+                .cpu_time
+                .zip(cpu_time())
+                .map(|(start, end)| end.saturating_sub(start)),
+            peak_gpu_memory: self.gpu_sampler.and_then(GpuSampler::stop),
+        }
+    }
+}
+
+/// Polls `nvidia-smi` on its own thread, keeping the highest GPU memory usage seen.
+struct GpuSampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Option<u64>>,
+}
+
+impl GpuSampler {
+    fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut peak = gpu_memory_used();
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::sleep(GPU_SAMPLING_INTERVAL);
+                    peak = peak.max(gpu_memory_used());
+                }
+                peak
+            }
+        });
+        Self { stop, handle }
+    }
+
+    fn stop(self) -> Option<u64> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().ok().flatten()
+    }
+}
+
+/// Resets the peak RSS the kernel reports for the process (`VmHWM`) to its current RSS.
+fn reset_peak_rss() -> bool {
+    cfg!(target_os = "linux") && std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// Peak RSS of the process since the last reset, read from `/proc/self/status`.
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// User and system CPU time of the process since it started.
+fn cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `getrusage` only writes to the struct it is given, which is initialized when it
+    // returns 0.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+/// Memory in use on all the GPUs, in bytes.
+fn gpu_memory_used() -> Option<u64> {
+    // nvidia-smi reports it in MiB, one line per GPU.
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=memory.used", "--format=csv,noheader,nounits"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let mib = String::from_utf8(output.stdout)
+        .ok()?
+        .lines()
+        .map(|line| line.trim().parse::<u64>().ok())
+        .sum::<Option<u64>>()?;
+    Some(mib * 1024 * 1024)
+}
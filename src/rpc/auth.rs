@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eyre::{OptionExt, WrapErr};
+use jsonwebtoken::{EncodingKey, Header};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{RequestBuilder, Url};
+use serde::Serialize;
+
+use crate::cli::RpcAuthOptions;
+
+/// Headers and credentials attached to every request sent to an RPC endpoint.
+#[derive(Clone, Default)]
+pub struct RpcAuth {
+    /// Extra headers, including the `Authorization` header of a static bearer token.
+    headers: HeaderMap,
+    /// Username and password for HTTP basic auth.
+    basic: Option<(String, String)>,
+    /// Secret used to sign a fresh JWT for each request, as done by the Engine API.
+    jwt_secret: Option<Vec<u8>>,
+}
+
+#[derive(Serialize)]
+struct JwtClaims {
+    iat: u64,
+}
+
+impl RpcAuth {
+    pub fn new(opts: &RpcAuthOptions) -> eyre::Result<Self> {
+        let mut headers = HeaderMap::new();
+
+        for header in &opts.rpc_headers {
+            let (name, value) = header.split_once(':').ok_or_eyre(format!(
+                "Invalid RPC header `{header}`, expected NAME:VALUE"
+            ))?;
+            headers.insert(
+                HeaderName::from_bytes(name.trim().as_bytes())
+                    .wrap_err_with(|| format!("Invalid RPC header name `{name}`"))?,
+                HeaderValue::from_str(value.trim())
+                    .wrap_err_with(|| format!("Invalid value for RPC header `{name}`"))?,
+            );
+        }
+
+        if let Some(path) = &opts.rpc_bearer_token_file {
+            let token = read_secret(path)?;
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .wrap_err("Invalid RPC bearer token")?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let basic = match (&opts.rpc_user, &opts.rpc_password_file) {
+            (Some(user), Some(path)) => Some((user.clone(), read_secret(path)?)),
+            _ => None,
+        };
+
+        let jwt_secret = opts
+            .rpc_jwt_secret
+            .as_ref()
+            .map(|path| -> eyre::Result<Vec<u8>> {
+                let secret = read_secret(path)?;
+                hex::decode(secret.trim_start_matches("0x"))
+                    .wrap_err_with(|| format!("JWT secret {} is not hex encoded", path.display()))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            headers,
+            basic,
+            jwt_secret,
+        })
+    }
+
+    /// Adds the configured headers and credentials to a request.
+    pub fn apply(&self, request: RequestBuilder) -> eyre::Result<RequestBuilder> {
+        let mut request = request.headers(self.headers.clone());
+
+        if let Some((user, password)) = &self.basic {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        if let Some(secret) = &self.jwt_secret {
+            // Tokens are only valid for a short time around `iat`, so a new one is
+            // signed for every request.
+            let claims = JwtClaims {
+                iat: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            };
+            let token = jsonwebtoken::encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret(secret),
+            )
+            .wrap_err("Failed to sign RPC JWT")?;
+            request = request.bearer_auth(token);
+        }
+
+        Ok(request)
+    }
+
+    /// Headers of a request to `url` with the configured credentials, used for the
+    /// WebSocket handshake.
+    pub fn headers(&self, url: &Url) -> eyre::Result<HeaderMap> {
+        let request = self.apply(super::CLIENT.get(url.clone()))?.build()?;
+        Ok(request.headers().clone())
+    }
+}
+
+fn read_secret(path: &Path) -> eyre::Result<String> {
+    let secret = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read secret file {}", path.display()))?;
+    Ok(secret.trim().to_string())
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use bytes::Bytes;
+use ethrex_common::types::AccountState;
+use ethrex_common::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+
+use super::{Account, NodeRLP};
+
+/// Snapshot of the data fetched by an `RpcDB` while building an execution witness.
+///
+/// It is written to the cache directory while the witness is being built, so that a run
+/// interrupted halfway can resume from it instead of fetching everything again.
+#[derive(Serialize, Deserialize)]
+pub struct RpcDBCheckpoint {
+    /// Block number the data was fetched for.
+    pub block_number: u64,
+    /// Accounts in the parent block, i.e. the initial state of the execution.
+    pub cache: HashMap<Address, CheckpointAccount>,
+    /// Accounts in the actual block being executed, i.e. the post-state.
+    pub child_cache: HashMap<Address, CheckpointAccount>,
+    /// Already fetched block hashes.
+    pub block_hashes: HashMap<u64, H256>,
+    /// Already fetched contract codes, hex encoded.
+    pub codes: HashMap<H256, String>,
+}
+
+/// Serializable counterpart of `Account`, with proofs and code hex encoded.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CheckpointAccount {
+    Existing {
+        nonce: u64,
+        balance: U256,
+        storage_root: H256,
+        code_hash: H256,
+        storage: HashMap<H256, U256>,
+        account_proof: Vec<String>,
+        storage_proofs: HashMap<H256, Vec<String>>,
+        code: Option<String>,
+    },
+    NonExisting {
+        account_proof: Vec<String>,
+        storage_proofs: HashMap<H256, Vec<String>>,
+    },
+}
+
+impl RpcDBCheckpoint {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Writes the checkpoint to a temporary file first, so that a crash while writing
+    /// never leaves a truncated checkpoint behind.
+    pub fn write(&self, path: &Path) -> eyre::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        let file = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(file, self)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+impl From<&Account> for CheckpointAccount {
+    fn from(account: &Account) -> Self {
+        match account {
+            Account::Existing {
+                account_state,
+                storage,
+                account_proof,
+                storage_proofs,
+                code,
+            } => CheckpointAccount::Existing {
+                nonce: account_state.nonce,
+                balance: account_state.balance,
+                storage_root: account_state.storage_root,
+                code_hash: account_state.code_hash,
+                storage: storage.clone(),
+                account_proof: encode_proof(account_proof),
+                storage_proofs: encode_storage_proofs(storage_proofs),
+                code: code.as_ref().map(hex::encode),
+            },
+            Account::NonExisting {
+                account_proof,
+                storage_proofs,
+            } => CheckpointAccount::NonExisting {
+                account_proof: encode_proof(account_proof),
+                storage_proofs: encode_storage_proofs(storage_proofs),
+            },
+        }
+    }
+}
+
+impl TryFrom<CheckpointAccount> for Account {
+    type Error = eyre::Report;
+
+    fn try_from(account: CheckpointAccount) -> eyre::Result<Self> {
+        Ok(match account {
+            CheckpointAccount::Existing {
+                nonce,
+                balance,
+                storage_root,
+                code_hash,
+                storage,
+                account_proof,
+                storage_proofs,
+                code,
+            } => Account::Existing {
+                account_state: AccountState {
+                    nonce,
+                    balance,
+                    storage_root,
+                    code_hash,
+                },
+                storage,
+                account_proof: decode_proof(account_proof)?,
+                storage_proofs: decode_storage_proofs(storage_proofs)?,
+                code: code.map(decode_code).transpose()?,
+            },
+            CheckpointAccount::NonExisting {
+                account_proof,
+                storage_proofs,
+            } => Account::NonExisting {
+                account_proof: decode_proof(account_proof)?,
+                storage_proofs: decode_storage_proofs(storage_proofs)?,
+            },
+        })
+    }
+}
+
+pub fn decode_code(code: String) -> eyre::Result<Bytes> {
+    Ok(Bytes::from(hex::decode(code)?))
+}
+
+fn encode_proof(proof: &[NodeRLP]) -> Vec<String> {
+    proof.iter().map(hex::encode).collect()
+}
+
+fn decode_proof(proof: Vec<String>) -> eyre::Result<Vec<NodeRLP>> {
+    Ok(proof
+        .into_iter()
+        .map(hex::decode)
+        .collect::<Result<_, _>>()?)
+}
+
+fn encode_storage_proofs(proofs: &HashMap<H256, Vec<NodeRLP>>) -> HashMap<H256, Vec<String>> {
+    proofs
+        .iter()
+        .map(|(key, proof)| (*key, encode_proof(proof)))
+        .collect()
+}
+
+fn decode_storage_proofs(
+    proofs: HashMap<H256, Vec<String>>,
+) -> eyre::Result<HashMap<H256, Vec<NodeRLP>>> {
+    proofs
+        .into_iter()
+        .map(|(key, proof)| Ok((key, decode_proof(proof)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use ethrex_common::types::AccountState;
+    use ethrex_common::{Address, H256, U256};
+
+    use super::{CheckpointAccount, RpcDBCheckpoint, decode_code};
+    use crate::rpc::Account;
+
+    fn existing_account() -> Account {
+        Account::Existing {
+            account_state: AccountState {
+                nonce: 7,
+                balance: U256::from(1_000_000),
+                storage_root: H256::repeat_byte(2),
+                code_hash: H256::repeat_byte(3),
+            },
+            storage: HashMap::from([(H256::repeat_byte(4), U256::from(42))]),
+            account_proof: vec![vec![0xf8, 0x01], vec![0x80]],
+            storage_proofs: HashMap::from([(H256::repeat_byte(4), vec![vec![0xc0]])]),
+            code: Some(Bytes::from_static(&[0x60, 0x00, 0x56])),
+        }
+    }
+
+    fn non_existing_account() -> Account {
+        Account::NonExisting {
+            account_proof: vec![vec![0xe2, 0x10]],
+            storage_proofs: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn checkpoint_round_trips_through_its_file() {
+        let path = std::env::temp_dir()
+            .join(format!("ethrex_replay_checkpoint_{}", std::process::id()))
+            .join("checkpoint_42.json");
+        let code = Bytes::from_static(&[0x60, 0x01]);
+
+        let checkpoint = RpcDBCheckpoint {
+            block_number: 42,
+            cache: HashMap::from([(Address::repeat_byte(1), (&existing_account()).into())]),
+            child_cache: HashMap::from([(
+                Address::repeat_byte(2),
+                (&non_existing_account()).into(),
+            )]),
+            block_hashes: HashMap::from([(41, H256::repeat_byte(5))]),
+            codes: HashMap::from([(H256::repeat_byte(6), hex::encode(&code))]),
+        };
+        checkpoint.write(&path).unwrap();
+
+        let resumed = RpcDBCheckpoint::load(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(resumed.block_number, 42);
+        assert_eq!(resumed.block_hashes, checkpoint.block_hashes);
+        assert_eq!(
+            decode_code(resumed.codes[&H256::repeat_byte(6)].clone()).unwrap(),
+            code
+        );
+
+        let restore = |mut accounts: HashMap<Address, CheckpointAccount>, address: Address| {
+            Account::try_from(accounts.remove(&address).unwrap()).unwrap()
+        };
+        assert_eq!(
+            format!("{:?}", restore(resumed.cache, Address::repeat_byte(1))),
+            format!("{:?}", existing_account())
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                restore(resumed.child_cache, Address::repeat_byte(2))
+            ),
+            format!("{:?}", non_existing_account())
+        );
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-use crate::rpc::{get_account, get_block, retry};
+use crate::rpc::checkpoint::{CheckpointAccount, RpcDBCheckpoint, decode_code};
+use crate::rpc::{RpcClient, get_account, get_block, retry};
 
 use bytes::Bytes;
 use ethrex_common::constants::EMPTY_KECCACK_HASH;
 use ethrex_common::types::block_execution_witness::RpcExecutionWitness;
-use ethrex_common::types::{AccountState, ChainConfig, Code, CodeMetadata, code_hash};
+use ethrex_common::types::{AccountState, BlockHeader, ChainConfig, Code, CodeMetadata, code_hash};
 use ethrex_common::{
     Address, H256, U256,
     types::{Block, TxKind},
@@ -25,9 +27,10 @@ use eyre::Context;
 use futures_util::future::join_all;
 use sha3::{Digest, Keccak256};
 use tokio::time::sleep;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, LazyLock};
 
 use super::{Account, NodeRLP};
@@ -41,13 +44,59 @@ pub static RPC_RPS: LazyLock<usize> = LazyLock::new(|| {
 pub static RATE_LIMIT: LazyLock<Duration> =
     LazyLock::new(|| Duration::from_micros(1_000_000 / *RPC_RPS as u64));
 
+/// Minimum time between two checkpoints of the data fetched by an `RpcDB`.
+pub static CHECKPOINT_INTERVAL: LazyLock<Duration> = LazyLock::new(|| {
+    env::var("REPLAY_CHECKPOINT_INTERVAL_SECS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(30))
+});
+
+/// Number of ancestors reachable through the `BLOCKHASH` opcode.
+const BLOCKHASH_WINDOW: u64 = 256;
+
+/// Times the missing ancestor headers are fetched again after cached ones were evicted
+/// because they didn't link with freshly fetched headers (e.g. after a reorg).
+const MAX_HEADER_REFETCHES: usize = 3;
+
+/// Cached ancestor headers, verified against their neighbours.
+type HeaderCache = Arc<Mutex<BTreeMap<u64, BlockHeader>>>;
+
+/// Ancestor headers fetched so far, per chain id and endpoint, shared by every `RpcDB` in
+/// the process so that consecutive blocks of a run don't fetch the same `BLOCKHASH` window
+/// again.
+static ANCESTOR_HEADERS: LazyLock<Mutex<HashMap<(u64, String), HeaderCache>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Whether headers out of the `BLOCKHASH` window are dropped from the header caches. Blocks
+/// fetched concurrently share those caches, so pruning for one of them could drop headers
+/// another is about to collect.
+static PRUNE_ANCESTOR_HEADERS: AtomicBool = AtomicBool::new(true);
+
+/// Keeps every ancestor header fetched for the rest of the process, for runs that fetch
+/// several blocks at once.
+pub fn keep_ancestor_headers() {
+    PRUNE_ANCESTOR_HEADERS.store(false, Ordering::Relaxed);
+}
+
+/// Header cache for the given chain and endpoint.
+fn ancestor_headers(chain_id: u64, client: &RpcClient) -> HeaderCache {
+    ANCESTOR_HEADERS
+        .lock()
+        .unwrap()
+        .entry((chain_id, client.url.to_string()))
+        .or_default()
+        .clone()
+}
+
 /// Structure for a database that fetches data from an RPC endpoint on demand.
 /// Caches already fetched data to minimize RPC calls.
 /// Implements the `LevmDatabase` trait to be used as the db for execution.
 #[derive(Clone)]
 pub struct RpcDB {
-    /// RPC endpoint URL.
-    pub rpc_url: String,
+    /// Client for the RPC endpoint.
+    pub client: RpcClient,
     /// Block number of the block we want to execute.
     pub block_number: u64,
     /// Cache of already fetched accounts. This includes state, code, storage and proofs.
@@ -60,21 +109,28 @@ pub struct RpcDB {
     pub block_hashes: Arc<Mutex<HashMap<u64, H256>>>,
     /// Cache of already fetched contract codes.
     pub codes: Arc<Mutex<HashMap<H256, Bytes>>>,
+    /// Cache of already fetched ancestor headers, verified against their neighbours.
+    pub headers: HeaderCache,
     /// Chain config of the blockchain.
     pub chain_config: ChainConfig,
     /// VM type (L1 or L2).
     pub vm_type: VMType,
+    /// File where fetched data is periodically checkpointed, if any.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Time of the last checkpoint.
+    last_checkpoint: Arc<Mutex<Instant>>,
 }
 
 impl RpcDB {
     pub fn new(
-        rpc_url: &str,
+        client: RpcClient,
         chain_config: ChainConfig,
         block_number: u64,
         vm_type: VMType,
     ) -> Self {
         RpcDB {
-            rpc_url: rpc_url.to_string(),
+            headers: ancestor_headers(chain_config.chain_id, &client),
+            client,
             block_number,
             cache: Arc::new(Mutex::new(HashMap::new())),
             child_cache: Arc::new(Mutex::new(HashMap::new())),
@@ -82,18 +138,28 @@ impl RpcDB {
             codes: Arc::new(Mutex::new(HashMap::new())),
             chain_config,
             vm_type,
+            checkpoint_path: None,
+            last_checkpoint: Arc::new(Mutex::new(Instant::now())),
         }
     }
 
     /// Create a new RpcDB and pre-cache all known accounts touched by the block.
+    ///
+    /// If a checkpoint path is given, the data fetched is periodically saved there, and
+    /// a checkpoint left by a previous run for the same block is resumed.
     pub async fn with_cache(
-        rpc_url: &str,
+        client: RpcClient,
         chain_config: ChainConfig,
         block_number: u64,
         block: &Block,
         vm_type: VMType,
+        checkpoint_path: Option<PathBuf>,
     ) -> eyre::Result<Self> {
-        let mut db = RpcDB::new(rpc_url, chain_config, block_number, vm_type);
+        let mut db = RpcDB::new(client, chain_config, block_number, vm_type);
+
+        if let Some(path) = checkpoint_path {
+            db.resume_from_checkpoint(path)?;
+        }
 
         db.cache_accounts(block).await?;
 
@@ -132,7 +198,7 @@ impl RpcDB {
                 .extend(keys);
         }
         let accounts: Vec<_> = accounts.into_iter().collect();
-        *self.cache.lock().unwrap() = self.fetch_accounts(&accounts, false).await?;
+        self.fetch_accounts(&accounts, false).await?;
 
         Ok(())
     }
@@ -165,6 +231,31 @@ impl RpcDB {
         let mut fetched = HashMap::new();
         let mut counter = 0;
 
+        // Accounts whose requested storage keys are all cached already (e.g. because they were
+        // restored from a checkpoint) are served from the cache instead of being fetched again.
+        let index: Vec<(Address, Vec<H256>)> = {
+            let cache = if from_child {
+                self.child_cache.lock().unwrap()
+            } else {
+                self.cache.lock().unwrap()
+            };
+            index
+                .iter()
+                .filter(|(address, storage_keys)| match cache.get(address) {
+                    Some(account)
+                        if storage_keys
+                            .iter()
+                            .all(|key| account.get_storage_proofs().contains_key(key)) =>
+                    {
+                        fetched.insert(*address, account.clone());
+                        false
+                    }
+                    _ => true,
+                })
+                .cloned()
+                .collect()
+        };
+
         // Fetch accounts in chunks to respect rate limits of the RPC endpoint
         for chunk in index.chunks(*RPC_RPS) {
             let start = Instant::now();
@@ -175,7 +266,7 @@ impl RpcDB {
                     *address,
                     retry(|| {
                         get_account(
-                            &self.rpc_url,
+                            &self.client,
                             block_number,
                             address,
                             storage_keys,
@@ -191,6 +282,8 @@ impl RpcDB {
                 .into_iter()
                 .collect::<eyre::Result<HashMap<_, _>>>()?;
 
+            self.merge_accounts(&fetched_chunk, from_child);
+            self.maybe_write_checkpoint();
             fetched.extend(fetched_chunk);
 
             if index.len() == 1 {
@@ -211,52 +304,22 @@ impl RpcDB {
             }
         }
 
-        // Merge fetched accounts into the appropriate cache based on the `from_child` flag.
-        // If from_child is true, we update the post-state cache (child_cache).
-        // Otherwise, we update the pre-state cache (cache).
-        // For existing cache entries, we merge storage and proof data.
-        if from_child {
-            let mut child_cache = self.child_cache.lock().unwrap();
-            for (address, account) in &fetched {
-                let acc_account_mut = child_cache.get_mut(address);
-                if let Some(cached_account) = acc_account_mut {
-                    // If already in cache, merge storage and proofs
-                    match (account, cached_account) {
-                        (
-                            Account::Existing {
-                                storage,
-                                storage_proofs,
-                                ..
-                            },
-                            Account::Existing {
-                                storage: storage_acc,
-                                storage_proofs: storage_proofs_acc,
-                                ..
-                            },
-                        ) => {
-                            storage_acc.extend(storage);
-                            storage_proofs_acc.extend(storage_proofs.clone());
-                        }
-                        (
-                            Account::NonExisting { storage_proofs, .. },
-                            Account::NonExisting {
-                                storage_proofs: storage_proofs_acc,
-                                ..
-                            },
-                        ) => {
-                            storage_proofs_acc.extend(storage_proofs.clone());
-                        }
-                        _ => {
-                            unreachable!()
-                        }
-                    };
-                } else {
-                    child_cache.insert(*address, account.clone());
-                }
-            }
-        } else {
-            let mut cache = self.cache.lock().unwrap();
-            for (address, account) in &fetched {
+        Ok(fetched)
+    }
+
+    /// Merges fetched accounts into the appropriate cache based on the `from_child` flag.
+    ///
+    /// If `from_child` is true, we update the post-state cache (child_cache).
+    /// Otherwise, we update the pre-state cache (cache).
+    /// For existing cache entries, we merge storage and proof data.
+    fn merge_accounts(&self, accounts: &HashMap<Address, Account>, from_child: bool) {
+        {
+            let mut cache = if from_child {
+                self.child_cache.lock().unwrap()
+            } else {
+                self.cache.lock().unwrap()
+            };
+            for (address, account) in accounts {
                 let acc_account_mut = cache.get_mut(address);
                 if let Some(cached_account) = acc_account_mut {
                     // If already in cache, merge storage and proofs
@@ -295,19 +358,15 @@ impl RpcDB {
             }
         }
         // Update code cache with any newly fetched code and hash it.
-        {
-            let mut codes = self.codes.lock().unwrap();
-            for account in fetched.values() {
-                if let Account::Existing {
-                    code: Some(code), ..
-                } = account
-                {
-                    codes.insert(code_hash(code), code.clone());
-                }
+        let mut codes = self.codes.lock().unwrap();
+        for account in accounts.values() {
+            if let Account::Existing {
+                code: Some(code), ..
+            } = account
+            {
+                codes.insert(code_hash(code), code.clone());
             }
         }
-
-        Ok(fetched)
     }
 
     /// Blocking version of fetch_accounts to be used inside LevmDatabase trait methods.
@@ -360,9 +419,8 @@ impl RpcDB {
             .collect();
 
         // fetch all of them, both before and after block execution
-        let initial_accounts = self.fetch_accounts_blocking(&index, false).unwrap();
-        let final_accounts = self.fetch_accounts_blocking(&index, true).unwrap();
-        // TODO: remove unwraps
+        let initial_accounts = self.fetch_accounts_blocking(&index, false)?;
+        let final_accounts = self.fetch_accounts_blocking(&index, true)?;
 
         let initial_account_proofs = initial_accounts
             .values()
@@ -422,7 +480,6 @@ impl RpcDB {
             })
             .collect();
 
-        let mut block_headers_bytes = Vec::new();
         let oldest_required_block_number = self
             .block_hashes
             .lock()
@@ -431,18 +488,32 @@ impl RpcDB {
             .min()
             .cloned()
             .unwrap_or(block.header.number - 1);
-        for number in oldest_required_block_number..block.header.number {
-            let handle = tokio::runtime::Handle::current();
-            let number_usize: usize = number
-                .try_into()
-                .wrap_err("failed to convert block number into usize")?;
-            let header = tokio::task::block_in_place(|| {
-                handle.block_on(get_block(&self.rpc_url, number_usize, false))
-            })
-            .wrap_err("failed to fetch block header")?
-            .header;
-            block_headers_bytes.push(Bytes::from(header.encode_to_vec()));
+        // Inserting the header of the block first evicts cached ancestors from another fork,
+        // so they are fetched again below.
+        self.insert_header(block.header.clone());
+        let handle = tokio::runtime::Handle::current();
+        let headers = tokio::task::block_in_place(|| {
+            handle.block_on(
+                self.fetch_ancestor_headers(oldest_required_block_number, block.header.number),
+            )
+        })
+        .wrap_err("failed to fetch ancestor headers")?;
+
+        // The parent must be the block the headers chain ends at, otherwise the
+        // endpoint is serving a different fork than the one the block belongs to.
+        let parent_hash = headers.last().map(|header| header.hash());
+        if parent_hash != Some(block.header.parent_hash) {
+            return Err(eyre::eyre!(
+                "Parent header of block {} doesn't match its parent hash",
+                block.header.number
+            ));
         }
+        self.prune_headers(block.header.number);
+
+        let block_headers_bytes: Vec<Bytes> = headers
+            .iter()
+            .map(|header| Bytes::from(header.encode_to_vec()))
+            .collect();
 
         let state_root = initial_account_proofs
             .clone()
@@ -480,6 +551,215 @@ impl RpcDB {
             headers: block_headers_bytes,
         })
     }
+
+    /// Restores the data saved in the checkpoint file, if it exists, and sets it as the
+    /// file to save future checkpoints to.
+    fn resume_from_checkpoint(&mut self, path: PathBuf) -> eyre::Result<()> {
+        if path.exists() {
+            let checkpoint = RpcDBCheckpoint::load(&path)
+                .wrap_err_with(|| format!("failed to load checkpoint {}", path.display()))?;
+
+            if checkpoint.block_number != self.block_number {
+                return Err(eyre::eyre!(
+                    "Checkpoint {} is for block {}, expected block {}",
+                    path.display(),
+                    checkpoint.block_number,
+                    self.block_number
+                ));
+            }
+
+            info!(
+                "Resuming witness construction for block {} from checkpoint ({} accounts, {} codes)",
+                self.block_number,
+                checkpoint.cache.len() + checkpoint.child_cache.len(),
+                checkpoint.codes.len()
+            );
+
+            let restore = |accounts: HashMap<Address, CheckpointAccount>| {
+                accounts
+                    .into_iter()
+                    .map(|(address, account)| Ok((address, Account::try_from(account)?)))
+                    .collect::<eyre::Result<HashMap<_, _>>>()
+            };
+            *self.cache.lock().unwrap() = restore(checkpoint.cache)?;
+            *self.child_cache.lock().unwrap() = restore(checkpoint.child_cache)?;
+            *self.block_hashes.lock().unwrap() = checkpoint.block_hashes;
+            *self.codes.lock().unwrap() = checkpoint
+                .codes
+                .into_iter()
+                .map(|(hash, code)| Ok((hash, decode_code(code)?)))
+                .collect::<eyre::Result<_>>()?;
+        }
+
+        self.checkpoint_path = Some(path);
+        Ok(())
+    }
+
+    /// Saves all the data fetched so far to the checkpoint file, if one is set.
+    pub fn write_checkpoint(&self) -> eyre::Result<()> {
+        let Some(path) = &self.checkpoint_path else {
+            return Ok(());
+        };
+
+        let checkpoint = RpcDBCheckpoint {
+            block_number: self.block_number,
+            cache: self
+                .cache
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(address, account)| (*address, account.into()))
+                .collect(),
+            child_cache: self
+                .child_cache
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(address, account)| (*address, account.into()))
+                .collect(),
+            block_hashes: self.block_hashes.lock().unwrap().clone(),
+            codes: self
+                .codes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(hash, code)| (*hash, hex::encode(code)))
+                .collect(),
+        };
+        checkpoint.write(path)?;
+        *self.last_checkpoint.lock().unwrap() = Instant::now();
+
+        debug!(
+            "Checkpointed fetched data for block {} to {}",
+            self.block_number,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Saves a checkpoint if `CHECKPOINT_INTERVAL` passed since the last one.
+    /// Failing to write it is not fatal, as it only prevents resuming later.
+    fn maybe_write_checkpoint(&self) {
+        if self.checkpoint_path.is_none()
+            || self.last_checkpoint.lock().unwrap().elapsed() < *CHECKPOINT_INTERVAL
+        {
+            return;
+        }
+        if let Err(e) = self.write_checkpoint() {
+            warn!("Failed to write checkpoint: {e}");
+        }
+    }
+
+    /// Removes the checkpoint file, once it's no longer needed.
+    pub fn delete_checkpoint(&self) -> eyre::Result<()> {
+        if let Some(path) = &self.checkpoint_path
+            && path.exists()
+        {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Fetches the headers of blocks `from..to`, reusing the ones already cached.
+    ///
+    /// Missing headers are requested concurrently in chunks of `RPC_RPS`, respecting the
+    /// rate limit, and each one is checked against its cached neighbours as it arrives.
+    /// Cached headers that don't link with the fetched ones are evicted and fetched again.
+    async fn fetch_ancestor_headers(&self, from: u64, to: u64) -> eyre::Result<Vec<BlockHeader>> {
+        for _ in 0..=MAX_HEADER_REFETCHES {
+            let missing: Vec<u64> = {
+                let headers = self.headers.lock().unwrap();
+                let missing: Vec<u64> = (from..to)
+                    .filter(|number| !headers.contains_key(number))
+                    .collect();
+                if missing.is_empty() {
+                    return Ok((from..to).map(|number| headers[&number].clone()).collect());
+                }
+                missing
+            };
+
+            debug!(
+                "Fetching {} of {} ancestor headers for block {to}",
+                missing.len(),
+                to - from
+            );
+
+            for chunk in missing.chunks(*RPC_RPS) {
+                let start = Instant::now();
+
+                let futures = chunk.iter().map(|number| async move {
+                    let rpc_block = retry(|| get_block(&self.client, *number as usize, false))
+                        .await
+                        .wrap_err(format!("failed to fetch header of block {number}"))?;
+                    Ok(rpc_block.header)
+                });
+
+                let fetched_chunk = join_all(futures)
+                    .await
+                    .into_iter()
+                    .collect::<eyre::Result<Vec<_>>>()?;
+
+                for header in fetched_chunk {
+                    self.insert_header(header);
+                }
+
+                // Cooldown depends on chunk size.
+                let target_gap = *RATE_LIMIT * chunk.len() as u32;
+                let elapsed = start.elapsed();
+
+                if target_gap > elapsed {
+                    sleep(target_gap - elapsed).await;
+                }
+            }
+        }
+
+        Err(eyre::eyre!(
+            "ancestor headers of block {to} kept changing while fetching them"
+        ))
+    }
+
+    /// Inserts a header into the cache, checking that it links with the headers of its
+    /// parent and child if those are already known.
+    ///
+    /// The inserted header was just fetched, so when a neighbour doesn't link with it the
+    /// cached headers on that side belong to another fork and are evicted.
+    fn insert_header(&self, header: BlockHeader) {
+        let mut headers = self.headers.lock().unwrap();
+        let number = header.number;
+
+        if let Some(parent) = number
+            .checked_sub(1)
+            .and_then(|parent_number| headers.get(&parent_number))
+            && parent.hash() != header.parent_hash
+        {
+            warn!(
+                "Header of block {number} doesn't link with the cached header of block {}, evicting older headers",
+                number - 1
+            );
+            headers.retain(|cached, _| *cached > number);
+        }
+
+        if let Some(child) = headers.get(&(number + 1))
+            && child.parent_hash != header.hash()
+        {
+            warn!(
+                "Header of block {} doesn't link with the cached header of block {number}, evicting newer headers",
+                number + 1
+            );
+            headers.retain(|cached, _| *cached < number);
+        }
+
+        headers.insert(number, header);
+    }
+
+    /// Drops cached headers that are out of the `BLOCKHASH` window of blocks after `block_number`.
+    fn prune_headers(&self, block_number: u64) {
+        if !PRUNE_ANCESTOR_HEADERS.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut headers = self.headers.lock().unwrap();
+        *headers = headers.split_off(&block_number.saturating_sub(BLOCKHASH_WINDOW));
+    }
 }
 
 impl LevmDatabase for RpcDB {
@@ -565,15 +845,28 @@ impl LevmDatabase for RpcDB {
         if let Some(hash) = self.block_hashes.lock().unwrap().get(&block_number) {
             return Ok(*hash);
         }
-        let handle = tokio::runtime::Handle::current();
-        let hash = tokio::task::block_in_place(|| {
-            handle.block_on(retry(|| {
-                get_block(&self.rpc_url, block_number as usize, false)
-            }))
-        })
-        .map_err(|e| DatabaseError::Custom(e.to_string()))?
-        .hash;
+        let cached_hash = self
+            .headers
+            .lock()
+            .unwrap()
+            .get(&block_number)
+            .map(|header| header.hash());
+        let hash = match cached_hash {
+            Some(hash) => hash,
+            None => {
+                let handle = tokio::runtime::Handle::current();
+                let rpc_block = tokio::task::block_in_place(|| {
+                    handle.block_on(retry(|| {
+                        get_block(&self.client, block_number as usize, false)
+                    }))
+                })
+                .map_err(|e| DatabaseError::Custom(e.to_string()))?;
+                self.insert_header(rpc_block.header);
+                rpc_block.hash
+            }
+        };
         self.block_hashes.lock().unwrap().insert(block_number, hash);
+        self.maybe_write_checkpoint();
         Ok(hash)
     }
 
@@ -632,3 +925,101 @@ pub fn get_potential_child_nodes(proof: &[NodeRLP], key: &PathRLP) -> Option<Vec
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethrex_common::H256;
+    use ethrex_common::types::{BlockHeader, ChainConfig};
+    use ethrex_levm::vm::VMType;
+    use reqwest::Url;
+
+    use super::{BLOCKHASH_WINDOW, RpcDB};
+    use crate::rpc::{RpcClient, auth::RpcAuth};
+
+    /// `RpcDB` with a header cache of its own, as the cache is shared per endpoint.
+    fn rpc_db(endpoint: &str) -> RpcDB {
+        let url = Url::parse(&format!("http://localhost/{endpoint}")).unwrap();
+        RpcDB::new(
+            RpcClient::new(url, RpcAuth::default()),
+            ChainConfig::default(),
+            0,
+            VMType::L1,
+        )
+    }
+
+    fn header(number: u64, parent_hash: H256, gas_limit: u64) -> BlockHeader {
+        BlockHeader {
+            number,
+            parent_hash,
+            gas_limit,
+            ..Default::default()
+        }
+    }
+
+    /// Headers `from..=to`, each linked to the previous one.
+    fn chain(from: u64, to: u64) -> Vec<BlockHeader> {
+        let mut parent_hash = H256::zero();
+        (from..=to)
+            .map(|number| {
+                let header = header(number, parent_hash, 0);
+                parent_hash = header.hash();
+                header
+            })
+            .collect()
+    }
+
+    fn cached_numbers(db: &RpcDB) -> Vec<u64> {
+        db.headers.lock().unwrap().keys().copied().collect()
+    }
+
+    #[test]
+    fn header_not_linking_with_child_evicts_newer_headers() {
+        let db = rpc_db("evict_newer");
+        let headers = chain(1, 4);
+        for header in headers.iter().cloned() {
+            db.insert_header(header);
+        }
+
+        db.insert_header(header(2, headers[0].hash(), 1));
+
+        assert_eq!(cached_numbers(&db), vec![1, 2]);
+        assert_eq!(db.headers.lock().unwrap()[&2].gas_limit, 1);
+    }
+
+    #[test]
+    fn header_not_linking_with_parent_evicts_older_headers() {
+        let db = rpc_db("evict_older");
+        for header in chain(1, 2) {
+            db.insert_header(header);
+        }
+
+        db.insert_header(header(3, H256::repeat_byte(1), 0));
+
+        assert_eq!(cached_numbers(&db), vec![3]);
+    }
+
+    #[test]
+    fn linked_headers_are_kept() {
+        let db = rpc_db("linked");
+        for header in chain(1, 4).into_iter().rev() {
+            db.insert_header(header);
+        }
+
+        assert_eq!(cached_numbers(&db), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn pruning_keeps_only_the_blockhash_window() {
+        let db = rpc_db("prune");
+        let last = BLOCKHASH_WINDOW + 10;
+        for header in chain(1, last) {
+            db.insert_header(header);
+        }
+
+        db.prune_headers(last + 1);
+
+        let numbers = cached_numbers(&db);
+        assert_eq!(numbers.first(), Some(&(last + 1 - BLOCKHASH_WINDOW)));
+        assert_eq!(numbers.last(), Some(&last));
+    }
+}
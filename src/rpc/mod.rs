@@ -1,16 +1,22 @@
 use std::collections::{BTreeMap, HashMap};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
 use std::time::Duration;
 
 use again::{RetryPolicy, Task};
 
 use bytes::Bytes;
-use ethrex_common::{Address, H256, U256, constants::EMPTY_KECCACK_HASH, types::AccountState};
+#[cfg(feature = "l2")]
+use ethrex_common::types::batch::Batch;
+use ethrex_common::types::block_execution_witness::RpcExecutionWitness;
+use ethrex_common::{
+    Address, Bloom, H256, U256, constants::EMPTY_KECCACK_HASH, types::AccountState,
+};
 use ethrex_rlp::decode::RLPDecode;
 use ethrex_rpc::types::block::RpcBlock;
 use ethrex_storage::hash_address;
 use ethrex_trie::{Node, Trie};
 
+use reqwest::Url;
 use serde::Deserialize;
 use serde::de::DeserializeOwned;
 use serde_json::json;
@@ -18,14 +24,175 @@ use serde_json::json;
 use lazy_static::lazy_static;
 use sha3::Digest;
 
+pub mod auth;
+pub mod checkpoint;
 pub mod db;
+pub mod transport;
+
+use auth::RpcAuth;
+use transport::{NewHeads, Transport};
 
 pub type NodeRLP = Vec<u8>;
 
 lazy_static! {
-    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(*REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to build HTTP client");
+}
+
+/// Time to wait for the response to a request, whether it's sent over HTTP or over a WebSocket
+/// or IPC connection. The witness is built from requests made while blocking the runtime, where
+/// `--fetch-timeout` can't fire, so this is what keeps a hung request from stalling a fetch.
+pub static REQUEST_TIMEOUT: LazyLock<Duration> = LazyLock::new(|| {
+    std::env::var("REPLAY_RPC_TIMEOUT_SECS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(300))
+});
+
+/// Pace of the requests sent by every client, set with `--rpc-rate-limit` so that blocks
+/// fetched concurrently stay under the limits of the endpoint.
+static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// Limits the requests of every client to `requests_per_second`, evenly spaced.
+pub fn limit_request_rate(requests_per_second: u32) {
+    let _ = RATE_LIMITER.set(RateLimiter {
+        interval: Duration::from_secs(1) / requests_per_second.max(1),
+        next_request: Mutex::new(tokio::time::Instant::now()),
+    });
+}
+
+struct RateLimiter {
+    interval: Duration,
+    next_request: Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    /// Waits for the next free slot, reserving it for the caller.
+    async fn acquire(&self) {
+        let slot = {
+            let mut next_request = self
+                .next_request
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let slot = (*next_request).max(tokio::time::Instant::now());
+            *next_request = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
 }
 
+/// Client for the JSON-RPC endpoint data is fetched from.
+/// Every request sent through it carries the headers and credentials configured for the endpoint.
+/// Requests go over HTTP, or over a shared persistent connection for `ws://`, `wss://` and `ipc://` URLs.
+#[derive(Clone)]
+pub struct RpcClient {
+    pub url: Url,
+    auth: Arc<RpcAuth>,
+    transport: Transport,
+}
+
+impl RpcClient {
+    pub fn new(url: Url, auth: RpcAuth) -> Self {
+        Self {
+            transport: Transport::from_url(&url),
+            url,
+            auth: Arc::new(auth),
+        }
+    }
+
+    /// Sends a JSON-RPC request and returns its deserialized result.
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> eyre::Result<T> {
+        if let Some(rate_limiter) = RATE_LIMITER.get() {
+            rate_limiter.acquire().await;
+        }
+
+        if self.transport.is_pubsub() {
+            let connection = transport::connection(&self.url, &self.auth).await?;
+            return get_result(connection.request(method, params).await?);
+        }
+
+        let request = &json!({
+            "id": 1,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        });
+        let response = self
+            .auth
+            .apply(CLIENT.post(self.url.clone()))?
+            .json(request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(eyre::eyre!(
+                "RPC endpoint responded with HTTP {status}: {body}"
+            ));
+        }
+        get_result(response.json::<serde_json::Value>().await?)
+    }
+
+    /// Subscribes to new chain heads. Returns `None` if the transport doesn't support
+    /// subscriptions (i.e. HTTP).
+    pub async fn subscribe_new_heads(&self) -> eyre::Result<Option<NewHeads>> {
+        if !self.transport.is_pubsub() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            NewHeads::subscribe(self.url.clone(), self.auth.clone()).await?,
+        ))
+    }
+}
+
+/// Error object of a JSON-RPC response, returned when the endpoint rejects a request
+/// (e.g. because the method is not supported).
+#[derive(Debug, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl JsonRpcError {
+    /// Code the JSON-RPC spec assigns to calls of methods that don't exist.
+    const METHOD_NOT_FOUND: i64 = -32601;
+
+    /// Whether the endpoint rejected the request because it doesn't implement or expose the
+    /// method. Some providers report it with a custom code, so the message is checked too.
+    pub fn is_method_not_supported(&self) -> bool {
+        let message = self.message.to_lowercase();
+        self.code == Self::METHOD_NOT_FOUND
+            || (message.contains("method")
+                && [
+                    "not supported",
+                    "unsupported",
+                    "not found",
+                    "not available",
+                    "does not exist",
+                ]
+                .iter()
+                .any(|reason| message.contains(reason)))
+    }
+}
+
+impl std::fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for JsonRpcError {}
+
 #[derive(Clone, Debug)]
 pub enum Account {
     Existing {
@@ -57,25 +224,96 @@ impl Account {
     }
 }
 
+pub async fn get_block_number(client: &RpcClient) -> eyre::Result<u64> {
+    let block_number: U256 = client.request("eth_blockNumber", json!([])).await?;
+    Ok(block_number.as_u64())
+}
+
+pub async fn get_chain_id(client: &RpcClient) -> eyre::Result<u64> {
+    let chain_id: U256 = client.request("eth_chainId", json!([])).await?;
+    Ok(chain_id.as_u64())
+}
+
 pub async fn get_block(
-    rpc_url: &str,
+    client: &RpcClient,
     block_number: usize,
     hydrated: bool,
 ) -> eyre::Result<RpcBlock> {
     let block_number = format!("0x{block_number:x}");
-    let request = &json!({
-        "id": 1,
-        "jsonrpc": "2.0",
-        "method": "eth_getBlockByNumber",
-        "params": [block_number, hydrated]
-    });
-    let response = CLIENT.post(rpc_url).json(request).send().await?;
-    let rpc_block: RpcBlock = get_result(response.json::<serde_json::Value>().await?)?;
-    Ok(rpc_block)
+    client
+        .request("eth_getBlockByNumber", json!([block_number, hydrated]))
+        .await
+}
+
+/// Gets the execution witness of a block, or of a range of blocks if `to` is set,
+/// through `debug_executionWitness`.
+pub async fn get_witness(
+    client: &RpcClient,
+    from: u64,
+    to: Option<u64>,
+) -> eyre::Result<RpcExecutionWitness> {
+    let params = match to {
+        Some(to) => json!([format!("0x{from:x}"), format!("0x{to:x}")]),
+        None => json!([format!("0x{from:x}")]),
+    };
+    client.request("debug_executionWitness", params).await
+}
+
+/// Receipt as returned by `eth_getBlockReceipts`, limited to the fields used for consensus.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt {
+    pub transaction_hash: H256,
+    /// Missing on pre-Byzantium receipts, which have a state root instead.
+    pub status: Option<U256>,
+    pub cumulative_gas_used: U256,
+    pub logs: Vec<RpcLog>,
+    pub logs_bloom: Bloom,
+}
+
+#[derive(Deserialize)]
+pub struct RpcLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: String,
+}
+
+impl RpcLog {
+    pub fn data(&self) -> eyre::Result<Bytes> {
+        Ok(Bytes::from(decode_hex(self.data.clone())?))
+    }
+}
+
+pub async fn get_block_receipts(
+    client: &RpcClient,
+    block_number: u64,
+) -> eyre::Result<Vec<RpcReceipt>> {
+    client
+        .request(
+            "eth_getBlockReceipts",
+            json!([format!("0x{block_number:x}")]),
+        )
+        .await
+}
+
+#[cfg(feature = "l2")]
+#[derive(Deserialize)]
+pub struct RpcBatch {
+    pub batch: Batch,
+}
+
+#[cfg(feature = "l2")]
+pub async fn get_batch_by_number(client: &RpcClient, batch_number: u64) -> eyre::Result<RpcBatch> {
+    client
+        .request(
+            "ethrex_getBatchByNumber",
+            json!([format!("0x{batch_number:x}"), true]),
+        )
+        .await
 }
 
 pub async fn get_account(
-    rpc_url: &str,
+    client: &RpcClient,
     block_number: usize,
     address: &Address,
     storage_keys: &[H256],
@@ -88,17 +326,6 @@ pub async fn get_account(
         .map(|key| format!("0x{key:x}"))
         .collect::<Vec<String>>();
 
-    let request = &json!(
-           {
-               "id": 1,
-               "jsonrpc": "2.0",
-               "method": "eth_getProof",
-               "params":[address_str, storage_keys, block_number_str]
-           }
-    );
-
-    let response = CLIENT.post(rpc_url).json(request).send().await?;
-
     #[derive(Deserialize)]
     #[serde(rename_all = "camelCase")]
     struct AccountProof {
@@ -124,7 +351,12 @@ pub async fn get_account(
         storage_hash,
         storage_proof,
         account_proof,
-    } = get_result(response.json::<serde_json::Value>().await?)?;
+    } = client
+        .request(
+            "eth_getProof",
+            json!([address_str, storage_keys, block_number_str]),
+        )
+        .await?;
 
     let account_proof = account_proof
         .into_iter()
@@ -176,7 +408,7 @@ pub async fn get_account(
         if let Some(cached_code) = codes.lock().unwrap().get(&account_state.code_hash) {
             Some(cached_code.clone())
         } else {
-            let fetched_code = get_code(rpc_url, block_number, address).await?;
+            let fetched_code = get_code(client, block_number, address).await?;
             let mut codes_lock = codes.lock().unwrap();
             codes_lock.insert(account_state.code_hash, fetched_code.clone());
             Some(fetched_code)
@@ -202,24 +434,26 @@ where
     policy.retry(|| fut.call()).await
 }
 
-async fn get_code(rpc_url: &str, block_number: usize, address: &Address) -> eyre::Result<Bytes> {
+async fn get_code(
+    client: &RpcClient,
+    block_number: usize,
+    address: &Address,
+) -> eyre::Result<Bytes> {
     let block_number = format!("0x{block_number:x}");
     let address = format!("0x{address:x}");
-    let request = &json!({
-        "id": 1,
-        "jsonrpc": "2.0",
-        "method": "eth_getCode",
-        "params": [address, block_number]
-    });
-
-    let response = CLIENT.post(rpc_url).json(request).send().await?;
-
-    let res = response.json::<serde_json::Value>().await?;
-    let owner_bytes = decode_hex(get_result(res)?)?;
+    let code: String = client
+        .request("eth_getCode", json!([address, block_number]))
+        .await?;
+    let owner_bytes = decode_hex(code)?;
     Ok(Bytes::from_owner(owner_bytes))
 }
 
 fn get_result<T: DeserializeOwned>(response: serde_json::Value) -> eyre::Result<T> {
+    if let Some(error) = response.get("error")
+        && let Ok(error) = serde_json::from_value::<JsonRpcError>(error.clone())
+    {
+        return Err(error.into());
+    }
     match response.get("result") {
         Some(result) => Ok(serde_json::from_value(result.clone())?),
         None => Err(eyre::Error::msg(format!(
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use ethrex_common::U256;
+use eyre::Context;
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tracing::{debug, warn};
+
+use super::REQUEST_TIMEOUT;
+use super::auth::RpcAuth;
+
+/// How requests reach an RPC endpoint, chosen from the scheme of its URL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// `http://` and `https://`, one HTTP request per JSON-RPC call.
+    Http,
+    /// `ws://` and `wss://`, a single persistent WebSocket connection.
+    Ws,
+    /// `ipc:///path/to/node.ipc`, a single persistent connection to a local IPC socket.
+    Ipc,
+}
+
+impl Transport {
+    pub fn from_url(url: &Url) -> Self {
+        match url.scheme() {
+            "ws" | "wss" => Transport::Ws,
+            "ipc" => Transport::Ipc,
+            _ => Transport::Http,
+        }
+    }
+
+    /// Whether the transport supports `eth_subscribe`.
+    pub fn is_pubsub(&self) -> bool {
+        !matches!(self, Transport::Http)
+    }
+}
+
+/// Open WebSocket and IPC connections, keyed by endpoint URL.
+///
+/// Every `RpcClient` pointing to the same endpoint shares its connection, so block,
+/// witness and proof requests of consecutive blocks all flow over it.
+static CONNECTIONS: LazyLock<tokio::sync::Mutex<HashMap<Url, Arc<PubSubConnection>>>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(HashMap::new()));
+
+/// Returns the open connection to the endpoint, connecting (again) if there is none.
+pub async fn connection(url: &Url, auth: &RpcAuth) -> eyre::Result<Arc<PubSubConnection>> {
+    let mut connections = CONNECTIONS.lock().await;
+
+    if let Some(connection) = connections.get(url)
+        && !connection.is_closed()
+    {
+        return Ok(connection.clone());
+    }
+
+    let connection = Arc::new(PubSubConnection::connect(url, auth).await?);
+    connections.insert(url.clone(), connection.clone());
+
+    Ok(connection)
+}
+
+/// Notifications kept for subscriptions whose id isn't registered yet, across all of them.
+/// Older ones are dropped first, as they likely belong to subscriptions no one listens to.
+const MAX_UNCLAIMED_NOTIFICATIONS: usize = 64;
+
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+type Subscriptions = Arc<Mutex<SubscriptionRouter>>;
+
+/// Subscriptions of a connection, by subscription id.
+#[derive(Default)]
+struct SubscriptionRouter {
+    senders: HashMap<String, mpsc::UnboundedSender<Value>>,
+    /// Notifications that arrived before `eth_subscribe` returned their subscription id,
+    /// handed over once the subscription is registered.
+    unclaimed: Vec<(String, Value)>,
+}
+
+impl SubscriptionRouter {
+    fn route(&mut self, id: &str, result: Value) {
+        if let Some(sender) = self.senders.get(id) {
+            let _ = sender.send(result);
+        } else {
+            if self.unclaimed.len() == MAX_UNCLAIMED_NOTIFICATIONS {
+                let (dropped_id, _) = self.unclaimed.remove(0);
+                debug!("Dropping notification of unknown subscription {dropped_id}");
+            }
+            self.unclaimed.push((id.to_string(), result));
+        }
+    }
+
+    fn register(&mut self, id: String) -> mpsc::UnboundedReceiver<Value> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for (_, result) in self
+            .unclaimed
+            .extract_if(.., |(unclaimed_id, _)| *unclaimed_id == id)
+        {
+            let _ = sender.send(result);
+        }
+        self.senders.insert(id, sender);
+        receiver
+    }
+}
+
+/// A persistent connection multiplexing JSON-RPC requests and subscriptions by id.
+pub struct PubSubConnection {
+    outgoing: mpsc::UnboundedSender<String>,
+    pending: PendingRequests,
+    subscriptions: Subscriptions,
+    next_id: AtomicU64,
+    closed: Arc<AtomicBool>,
+}
+
+impl PubSubConnection {
+    async fn connect(url: &Url, auth: &RpcAuth) -> eyre::Result<Self> {
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+        let (incoming, mut incoming_rx) = mpsc::unbounded_channel::<Value>();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        match Transport::from_url(url) {
+            Transport::Ws => {
+                let mut request = url.as_str().into_client_request()?;
+                request.headers_mut().extend(auth.headers(url)?);
+
+                let (stream, _) = tokio_tungstenite::connect_async(request).await?;
+                let (mut sink, mut stream) = stream.split();
+
+                tokio::spawn(async move {
+                    while let Some(message) = outgoing_rx.recv().await {
+                        if let Err(e) = sink.send(Message::text(message)).await {
+                            warn!("Failed to send message over WebSocket: {e}");
+                            break;
+                        }
+                    }
+                });
+
+                let closed = closed.clone();
+                tokio::spawn(async move {
+                    while let Some(message) = stream.next().await {
+                        match message {
+                            Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                                Ok(value) => {
+                                    let _ = incoming.send(value);
+                                }
+                                Err(e) => warn!("Ignoring malformed RPC message: {e}"),
+                            },
+                            Ok(Message::Close(_)) => break,
+                            Ok(_) => {}
+                            Err(e) => {
+                                warn!("WebSocket connection to RPC failed: {e}");
+                                break;
+                            }
+                        }
+                    }
+                    closed.store(true, Ordering::Relaxed);
+                });
+            }
+            Transport::Ipc => {
+                let stream = UnixStream::connect(url.path()).await?;
+                let (mut reader, mut writer) = stream.into_split();
+
+                tokio::spawn(async move {
+                    while let Some(message) = outgoing_rx.recv().await {
+                        if let Err(e) = writer.write_all(message.as_bytes()).await {
+                            warn!("Failed to write to IPC socket: {e}");
+                            break;
+                        }
+                    }
+                });
+
+                let closed = closed.clone();
+                tokio::spawn(async move {
+                    // Messages are concatenated JSON values without any framing, so
+                    // incomplete trailing data is kept until the rest of it arrives.
+                    let mut buffer = Vec::new();
+                    let mut chunk = [0u8; 64 * 1024];
+                    loop {
+                        match reader.read(&mut chunk).await {
+                            Ok(0) => break,
+                            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                            Err(e) => {
+                                warn!("IPC connection to RPC failed: {e}");
+                                break;
+                            }
+                        }
+
+                        let mut values =
+                            serde_json::Deserializer::from_slice(&buffer).into_iter::<Value>();
+                        let mut consumed = 0;
+                        loop {
+                            match values.next() {
+                                Some(Ok(value)) => {
+                                    consumed = values.byte_offset();
+                                    let _ = incoming.send(value);
+                                }
+                                Some(Err(e)) if e.is_eof() => break,
+                                Some(Err(e)) => {
+                                    warn!("Ignoring malformed RPC message: {e}");
+                                    consumed = buffer.len();
+                                    break;
+                                }
+                                None => break,
+                            }
+                        }
+                        buffer.drain(..consumed);
+                    }
+                    closed.store(true, Ordering::Relaxed);
+                });
+            }
+            Transport::Http => eyre::bail!("{url} is not a WebSocket or IPC endpoint"),
+        }
+
+        let pending = PendingRequests::default();
+        let subscriptions = Subscriptions::default();
+
+        // Routes responses to the request waiting for them and notifications to their
+        // subscription. Once the connection is gone, dropping the senders makes every
+        // waiting request fail and ends every subscription.
+        {
+            let pending = pending.clone();
+            let subscriptions = subscriptions.clone();
+            let closed = closed.clone();
+            tokio::spawn(async move {
+                while let Some(message) = incoming_rx.recv().await {
+                    if let Some(id) = message.get("id").and_then(Value::as_u64) {
+                        if let Some(sender) = pending.lock().unwrap().remove(&id) {
+                            let _ = sender.send(message);
+                        }
+                    } else if message.get("method").and_then(Value::as_str)
+                        == Some("eth_subscription")
+                    {
+                        let params = &message["params"];
+                        if let Some(id) = params["subscription"].as_str() {
+                            subscriptions
+                                .lock()
+                                .unwrap()
+                                .route(id, params["result"].clone());
+                        }
+                    } else {
+                        debug!("Ignoring unexpected RPC message: {message}");
+                    }
+                }
+                // Requests check `closed` while holding the lock, so none can be added
+                // after this and wait forever.
+                let mut pending = pending.lock().unwrap();
+                closed.store(true, Ordering::Relaxed);
+                pending.clear();
+                *subscriptions.lock().unwrap() = SubscriptionRouter::default();
+            });
+        }
+
+        Ok(Self {
+            outgoing,
+            pending,
+            subscriptions,
+            next_id: AtomicU64::new(1),
+            closed,
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Sends a JSON-RPC request and returns the whole response object.
+    ///
+    /// Fails if no response arrives within `REPLAY_RPC_TIMEOUT_SECS`.
+    pub async fn request(&self, method: &str, params: Value) -> eyre::Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if self.is_closed() {
+                eyre::bail!("Connection to RPC endpoint is closed");
+            }
+            pending.insert(id, sender);
+        }
+
+        let request = json!({
+            "id": id,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        });
+        if self.outgoing.send(request.to_string()).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            eyre::bail!("Connection to RPC endpoint is closed");
+        }
+
+        match tokio::time::timeout(*REQUEST_TIMEOUT, receiver).await {
+            Ok(response) => response.map_err(|_| {
+                eyre::eyre!("Connection to RPC endpoint closed before `{method}` returned")
+            }),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                eyre::bail!(
+                    "`{method}` didn't return within {}s",
+                    REQUEST_TIMEOUT.as_secs()
+                )
+            }
+        }
+    }
+
+    /// Subscribes through `eth_subscribe` and returns the stream of notification results.
+    pub async fn subscribe(&self, params: Value) -> eyre::Result<mpsc::UnboundedReceiver<Value>> {
+        let response = self.request("eth_subscribe", params).await?;
+        let id: String = super::get_result(response)?;
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if self.is_closed() {
+            eyre::bail!("Connection to RPC endpoint closed before subscribing");
+        }
+        Ok(subscriptions.register(id))
+    }
+}
+
+/// Stream of new chain heads, received through `eth_subscribe("newHeads")`.
+///
+/// If the connection drops, the endpoint is connected to again and the subscription
+/// renewed. Heads announced while disconnected are missed.
+pub struct NewHeads {
+    url: Url,
+    auth: Arc<RpcAuth>,
+    receiver: mpsc::UnboundedReceiver<Value>,
+}
+
+impl NewHeads {
+    pub(super) async fn subscribe(url: Url, auth: Arc<RpcAuth>) -> eyre::Result<Self> {
+        let receiver = Self::open(&url, &auth).await?;
+        Ok(Self {
+            url,
+            auth,
+            receiver,
+        })
+    }
+
+    async fn open(url: &Url, auth: &RpcAuth) -> eyre::Result<mpsc::UnboundedReceiver<Value>> {
+        connection(url, auth)
+            .await?
+            .subscribe(json!(["newHeads"]))
+            .await
+    }
+
+    /// Waits for the next head and returns its block number.
+    pub async fn next(&mut self) -> eyre::Result<u64> {
+        #[derive(Deserialize)]
+        struct Head {
+            number: U256,
+        }
+
+        let head = loop {
+            if let Some(head) = self.receiver.recv().await {
+                break head;
+            }
+            warn!("`newHeads` subscription ended, subscribing again");
+            self.receiver = super::retry(|| Self::open(&self.url, &self.auth))
+                .await
+                .wrap_err("failed to renew the `newHeads` subscription")?;
+        };
+        let head: Head = serde_json::from_value(head)?;
+
+        Ok(head.number.as_u64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{MAX_UNCLAIMED_NOTIFICATIONS, SubscriptionRouter};
+
+    #[test]
+    fn notifications_before_registration_are_handed_over() {
+        let mut router = SubscriptionRouter::default();
+        router.route("0x1", json!(1));
+        router.route("0x2", json!("other"));
+        router.route("0x1", json!(2));
+
+        let mut receiver = router.register("0x1".to_string());
+        router.route("0x1", json!(3));
+
+        assert_eq!(receiver.try_recv().unwrap(), json!(1));
+        assert_eq!(receiver.try_recv().unwrap(), json!(2));
+        assert_eq!(receiver.try_recv().unwrap(), json!(3));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(router.unclaimed, vec![("0x2".to_string(), json!("other"))]);
+    }
+
+    #[test]
+    fn oldest_unclaimed_notifications_are_dropped_first() {
+        let mut router = SubscriptionRouter::default();
+        for n in 0..MAX_UNCLAIMED_NOTIFICATIONS + 2 {
+            router.route("0x1", json!(n));
+        }
+
+        assert_eq!(router.unclaimed.len(), MAX_UNCLAIMED_NOTIFICATIONS);
+        let mut receiver = router.register("0x1".to_string());
+        assert_eq!(receiver.try_recv().unwrap(), json!(2));
+        assert!(router.unclaimed.is_empty());
+    }
+}
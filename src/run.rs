@@ -1,11 +1,22 @@
-use crate::{cache::Cache, cli::ProofType};
+#[cfg(not(feature = "l2"))]
+use crate::output_verification::{GuestOutput, decode_program_output};
+use crate::{
+    cache::Cache,
+    cli::ProofType,
+    overrides::{Overrides, apply_state_override, check_state_override},
+    proofs::{SavedProof, serialize_proof},
+    resource_usage::{UsagePhase, UsageRecorder},
+};
 #[cfg(feature = "l2")]
 use ethrex_common::types::{ELASTICITY_MULTIPLIER, fee_config::FeeConfig};
 use ethrex_common::{
-    H256,
-    types::{AccountUpdate, Receipt, block_execution_witness::GuestProgramState},
+    Address, H256, U256,
+    tracing::CallTrace,
+    types::{
+        AccountState, AccountUpdate, Block, Receipt, block_execution_witness::GuestProgramState,
+    },
 };
-use ethrex_guest_program::input::ProgramInput;
+use ethrex_guest_program::{execution::execution_program, input::ProgramInput};
 use ethrex_levm::{db::gen_db::GeneralizedDatabase, vm::VMType};
 #[cfg(feature = "openvm")]
 use ethrex_prover::OpenVmBackend;
@@ -17,39 +28,80 @@ use ethrex_prover::Sp1Backend;
 use ethrex_prover::ZiskBackend;
 use ethrex_prover::{BackendType, ExecBackend, ProverBackend};
 use ethrex_rpc::debug::execution_witness::execution_witness_from_rpc_chain_config;
-use ethrex_vm::{DynVmDatabase, Evm, GuestProgramStateWrapper, backends::levm::LEVM};
+use ethrex_vm::{DynVmDatabase, Evm, GuestProgramStateWrapper, VmDatabase, backends::levm::LEVM};
 use eyre::Context;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    ops::Range,
     panic::{AssertUnwindSafe, catch_unwind},
+    path::PathBuf,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
+use tracing::{info, warn};
 
-pub async fn exec(backend: BackendType, cache: Cache) -> eyre::Result<Duration> {
-    #[cfg(feature = "l2")]
-    let input = get_l2_input(cache)?;
-    #[cfg(not(feature = "l2"))]
-    let input = get_l1_input(cache)?;
+/// Cycles reported by a zkVM while executing the guest program. Unlike the execution time,
+/// they don't depend on the hardware.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CycleCount {
+    pub cycles: u64,
+    /// Number of segments the execution was split into, for zkVMs that report it.
+    pub segments: Option<u64>,
+}
+
+/// Result of executing the guest program with a backend.
+pub struct Execution {
+    pub elapsed: Duration,
+    /// Cycles reported by the zkVM. Only SP1 and RISC0 expose them for now.
+    pub cycles: Option<CycleCount>,
+    /// Guest program output committed by the execution, encoded by `ProgramOutput::encode`.
+    /// `None` for ZisK and OpenVM, whose backends don't return it.
+    pub public_values: Option<Vec<u8>>,
+}
+
+/// Executes the blocks of the cache, returning the execution time along with the cycles and
+/// the public values reported by the backend, if it reports them.
+pub async fn exec_with_output(
+    backend: BackendType,
+    cache: Cache,
+    usage: UsageRecorder,
+) -> eyre::Result<Execution> {
+    let input = usage.measure_once(UsagePhase::Input, || get_input(cache))?;
 
     // Use catch_unwind to capture panics
     let result = catch_unwind(AssertUnwindSafe(|| match backend {
-        BackendType::Exec => ExecBackend::new().execute_timed(input),
+        BackendType::Exec => usage.measure(UsagePhase::Execution, || execute_natively(input)),
         #[cfg(feature = "sp1")]
-        BackendType::SP1 => Sp1Backend::new().execute_timed(input),
+        BackendType::SP1 => execute_sp1(input, &usage),
         #[cfg(feature = "risc0")]
-        BackendType::RISC0 => Risc0Backend::new().execute_timed(input),
+        BackendType::RISC0 => execute_risc0(input, &usage),
         #[cfg(feature = "zisk")]
-        BackendType::ZisK => ZiskBackend::new().execute_timed(input),
+        BackendType::ZisK => usage
+            .measure(UsagePhase::Execution, || {
+                ZiskBackend::new().execute_timed(input)
+            })
+            .map(|elapsed| Execution {
+                elapsed,
+                cycles: None,
+                public_values: None,
+            })
+            .map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e))),
         #[cfg(feature = "openvm")]
-        BackendType::OpenVM => OpenVmBackend::new().execute_timed(input),
+        BackendType::OpenVM => usage
+            .measure(UsagePhase::Execution, || {
+                OpenVmBackend::new().execute_timed(input)
+            })
+            .map(|elapsed| Execution {
+                elapsed,
+                cycles: None,
+                public_values: None,
+            })
+            .map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e))),
     }));
 
     match result {
-        Ok(exec_result) => {
-            let elapsed =
-                exec_result.map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
-            Ok(elapsed)
-        }
+        Ok(execution) => execution,
         Err(panic_info) => {
             // Try to extract meaningful error message from panic info
             let panic_msg = extract_panic_message(&panic_info);
@@ -62,62 +114,521 @@ pub async fn exec(backend: BackendType, cache: Cache) -> eyre::Result<Duration>
     }
 }
 
+/// Runs the guest program natively, as `ExecBackend` does, keeping its output.
+fn execute_natively(input: ProgramInput) -> eyre::Result<Execution> {
+    let start = Instant::now();
+    let output = execution_program(input)
+        .map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
+
+    Ok(Execution {
+        elapsed: start.elapsed(),
+        cycles: None,
+        public_values: Some(output.encode()),
+    })
+}
+
+/// SP1 client, built once as it's expensive to set up.
+#[cfg(feature = "sp1")]
+static SP1_CLIENT: std::sync::OnceLock<sp1_sdk::EnvProver> = std::sync::OnceLock::new();
+
+#[cfg(feature = "sp1")]
+pub(crate) fn sp1_client() -> &'static sp1_sdk::EnvProver {
+    SP1_CLIENT.get_or_init(sp1_sdk::ProverClient::from_env)
+}
+
+/// Executes the guest program with the SP1 executor, which reports the instructions it ran.
+/// Serialization, client setup and execution are measured as phases of their own, and the
+/// elapsed time covers all three, as `ProverBackend::execute_timed` does.
+#[cfg(feature = "sp1")]
+fn execute_sp1(input: ProgramInput, usage: &UsageRecorder) -> eyre::Result<Execution> {
+    let start = Instant::now();
+    let stdin = usage
+        .measure(UsagePhase::Serialization, || {
+            Sp1Backend::new().serialize_input(&input)
+        })
+        .map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
+    let client = usage.measure(UsagePhase::Setup, sp1_client);
+
+    let execution = usage.measure(UsagePhase::Execution, || {
+        client
+            .execute(ethrex_guest_program::ZKVM_SP1_PROGRAM_ELF, &stdin)
+            .run()
+    });
+    let elapsed = start.elapsed();
+    let (public_values, report) =
+        execution.map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
+
+    Ok(Execution {
+        elapsed,
+        cycles: Some(CycleCount {
+            cycles: report.total_instruction_count(),
+            segments: None,
+        }),
+        public_values: Some(public_values.to_vec()),
+    })
+}
+
+/// Executes the guest program with the RISC0 executor, which reports cycles and segments.
+/// Serialization, executor setup and execution are measured as phases of their own, and the
+/// elapsed time covers all three, as `ProverBackend::execute_timed` does.
+#[cfg(feature = "risc0")]
+fn execute_risc0(input: ProgramInput, usage: &UsageRecorder) -> eyre::Result<Execution> {
+    let start = Instant::now();
+    let env = usage
+        .measure(UsagePhase::Serialization, || {
+            Risc0Backend::new().serialize_input(&input)
+        })
+        .map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
+    let executor = usage.measure(UsagePhase::Setup, risc0_zkvm::default_executor);
+
+    let session = usage.measure(UsagePhase::Execution, || {
+        executor.execute(env, ethrex_guest_program::ZKVM_RISC0_PROGRAM_ELF)
+    });
+    let elapsed = start.elapsed();
+    let session = session.map_err(|e| eyre::Error::msg(format!("Execution failed: {}", e)))?;
+
+    Ok(Execution {
+        elapsed,
+        cycles: Some(CycleCount {
+            cycles: session.cycles(),
+            segments: Some(session.segments.len() as u64),
+        }),
+        public_values: session
+            .journal
+            .as_ref()
+            .map(|journal| journal.bytes.clone()),
+    })
+}
+
+/// Outcome of executing a block with one of the backends compiled in.
+#[cfg(not(feature = "l2"))]
+pub struct BackendExecution {
+    pub backend: BackendType,
+    /// Output committed by the guest program, or `None` if the backend doesn't return it, in
+    /// which case it can't be compared with the others.
+    pub result: eyre::Result<Option<GuestOutput>>,
+}
+
+/// Backends enabled through features, plus the native execution backend.
+pub fn compiled_backends() -> Vec<BackendType> {
+    #[allow(unused_mut)]
+    let mut backends = vec![BackendType::Exec];
+    #[cfg(feature = "sp1")]
+    backends.push(BackendType::SP1);
+    #[cfg(feature = "risc0")]
+    backends.push(BackendType::RISC0);
+    #[cfg(feature = "zisk")]
+    backends.push(BackendType::ZisK);
+    #[cfg(feature = "openvm")]
+    backends.push(BackendType::OpenVM);
+    backends
+}
+
+/// Executes the block of the cache with every backend compiled in, building the same
+/// `ProgramInput` for each of them, and decodes the output each of them committed.
+#[cfg(not(feature = "l2"))]
+pub async fn exec_all_backends(cache: Cache) -> Vec<BackendExecution> {
+    let mut executions = Vec::new();
+
+    for backend in compiled_backends() {
+        let result = exec_with_output(backend, cache.clone(), UsageRecorder::default())
+            .await
+            .and_then(|execution| {
+                execution
+                    .public_values
+                    .map(|public_values| decode_program_output(&public_values))
+                    .transpose()
+            });
+
+        executions.push(BackendExecution { backend, result });
+    }
+
+    executions
+}
+
+/// Result of proving the guest program with a backend.
+pub struct Proving {
+    pub elapsed: Duration,
+    /// Guest program output the proof commits to, encoded by `ProgramOutput::encode`. `None`
+    /// for ZisK and OpenVM, whose proofs aren't decoded.
+    pub public_values: Option<Vec<u8>>,
+}
+
+/// Proves the blocks of the cache and saves the proof under `proofs_dir`.
 pub async fn prove(
     backend: BackendType,
     proof_type: ProofType,
     cache: Cache,
-) -> eyre::Result<Duration> {
-    #[cfg(feature = "l2")]
-    let input = get_l2_input(cache)?;
-    #[cfg(not(feature = "l2"))]
-    let input = get_l1_input(cache)?;
+    proofs_dir: PathBuf,
+    usage: UsageRecorder,
+) -> eyre::Result<Proving> {
+    let input = usage.measure_once(UsagePhase::Input, || get_input(cache.clone()))?;
 
     // Use catch_unwind to capture panics
     let result = catch_unwind(AssertUnwindSafe(|| match backend {
-        BackendType::Exec => ExecBackend::new()
-            .prove_timed(input, proof_type.into())
-            .map(|(_, duration)| duration),
+        BackendType::Exec => usage.measure(UsagePhase::Proving, || {
+            prove_with(ExecBackend::new(), input, proof_type.clone(), |output| {
+                Some(output.encode())
+            })
+        }),
         #[cfg(feature = "sp1")]
-        BackendType::SP1 => Sp1Backend::new()
-            .prove_timed(input, proof_type.into())
-            .map(|(_, duration)| duration),
+        BackendType::SP1 => prove_sp1(input, &proof_type, &usage),
         #[cfg(feature = "risc0")]
-        BackendType::RISC0 => Risc0Backend::new()
-            .prove_timed(input, proof_type.into())
-            .map(|(_, duration)| duration),
+        BackendType::RISC0 => prove_risc0(input, &proof_type, &usage),
         #[cfg(feature = "zisk")]
-        BackendType::ZisK => ZiskBackend::new()
-            .prove_timed(input, proof_type.into())
-            .map(|(_, duration)| duration),
+        BackendType::ZisK => usage.measure(UsagePhase::Proving, || {
+            prove_with(ZiskBackend::new(), input, proof_type.clone(), |_| None)
+        }),
         #[cfg(feature = "openvm")]
-        BackendType::OpenVM => OpenVmBackend::new()
-            .prove_timed(input, proof_type.into())
-            .map(|(_, duration)| duration),
+        BackendType::OpenVM => usage.measure(UsagePhase::Proving, || {
+            prove_with(OpenVmBackend::new(), input, proof_type.clone(), |_| None)
+        }),
     }));
 
-    match result {
-        Ok(prove_result) => {
-            let elapsed =
-                prove_result.map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?;
-            Ok(elapsed)
-        }
+    let (proof, proving) = match result {
+        Ok(prove_result) => prove_result?,
         Err(panic_info) => {
             // Try to extract meaningful error message from panic info
             let panic_msg = extract_panic_message(&panic_info);
 
-            Err(eyre::Error::msg(format!("Proving panicked: {}", panic_msg)))
+            return Err(eyre::Error::msg(format!("Proving panicked: {}", panic_msg)));
         }
+    };
+
+    let saved_proof = SavedProof::new(
+        backend,
+        proof_type,
+        &cache,
+        proving.public_values.clone(),
+        proof,
+    )?;
+    match saved_proof.save(&proofs_dir) {
+        Ok(path) => info!("Saved proof to {}", path.display()),
+        Err(e) => warn!("Failed to save proof: {e}"),
     }
+
+    Ok(proving)
 }
 
-pub async fn run_tx(cache: Cache, tx_hash: H256) -> eyre::Result<(Receipt, Vec<AccountUpdate>)> {
-    let block = cache
-        .blocks
-        .first()
-        .ok_or(eyre::Error::msg("missing block data"))?;
+/// Proves the input with the backend, returning the serialized proof along with the public
+/// values `public_values` reads from it.
+fn prove_with<B: ProverBackend>(
+    backend: B,
+    input: ProgramInput,
+    proof_type: ProofType,
+    public_values: impl FnOnce(&B::ProofOutput) -> Option<Vec<u8>>,
+) -> eyre::Result<(serde_json::Value, Proving)>
+where
+    B::ProofOutput: Serialize,
+{
+    let (proof, elapsed) = backend
+        .prove_timed(input, proof_type.into())
+        .map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?;
+
+    let proving = Proving {
+        elapsed,
+        public_values: public_values(&proof),
+    };
+
+    Ok((serialize_proof::<B>(&proof)?, proving))
+}
+
+/// Proves the input with the SP1 prover. Serialization, client setup, key setup and proving
+/// are measured as phases of their own, and the elapsed time covers all of them, as
+/// `ProverBackend::prove_timed` does.
+#[cfg(feature = "sp1")]
+fn prove_sp1(
+    input: ProgramInput,
+    proof_type: &ProofType,
+    usage: &UsageRecorder,
+) -> eyre::Result<(serde_json::Value, Proving)> {
+    let start = Instant::now();
+    let stdin = usage
+        .measure_once(UsagePhase::Serialization, || {
+            Sp1Backend::new().serialize_input(&input)
+        })
+        .map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?;
+    let client = usage.measure_once(UsagePhase::Setup, sp1_client);
+    let (proving_key, _) = usage.measure(UsagePhase::KeySetup, || {
+        client.setup(ethrex_guest_program::ZKVM_SP1_PROGRAM_ELF)
+    });
+
+    let proof = usage.measure(UsagePhase::Proving, || {
+        let prove = client.prove(&proving_key, &stdin);
+        match proof_type {
+            ProofType::Compressed => prove.compressed().run(),
+            ProofType::Groth16 => prove.groth16().run(),
+        }
+    });
+    let elapsed = start.elapsed();
+    let proof = proof.map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?;
+
+    let proving = Proving {
+        elapsed,
+        public_values: Some(proof.public_values.to_vec()),
+    };
+
+    Ok((
+        serde_json::to_value(&proof).wrap_err("Failed to serialize proof")?,
+        proving,
+    ))
+}
+
+/// Proves the input with the RISC0 prover. Serialization, prover setup and proving are
+/// measured as phases of their own, and the elapsed time covers all of them, as
+/// `ProverBackend::prove_timed` does. RISC0 has no keys to set up.
+#[cfg(feature = "risc0")]
+fn prove_risc0(
+    input: ProgramInput,
+    proof_type: &ProofType,
+    usage: &UsageRecorder,
+) -> eyre::Result<(serde_json::Value, Proving)> {
+    let start = Instant::now();
+    let env = usage
+        .measure_once(UsagePhase::Serialization, || {
+            Risc0Backend::new().serialize_input(&input)
+        })
+        .map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?;
+    let prover = usage.measure_once(UsagePhase::Setup, risc0_zkvm::default_prover);
+
+    let opts = match proof_type {
+        ProofType::Compressed => risc0_zkvm::ProverOpts::succinct(),
+        ProofType::Groth16 => risc0_zkvm::ProverOpts::groth16(),
+    };
+    let prove_info = usage.measure(UsagePhase::Proving, || {
+        prover.prove_with_opts(env, ethrex_guest_program::ZKVM_RISC0_PROGRAM_ELF, &opts)
+    });
+    let elapsed = start.elapsed();
+    let receipt = prove_info
+        .map_err(|e| eyre::Error::msg(format!("Proving failed: {}", e)))?
+        .receipt;
+
+    let proving = Proving {
+        elapsed,
+        public_values: Some(receipt.journal.bytes.clone()),
+    };
+
+    Ok((
+        serde_json::to_value(&receipt).wrap_err("Failed to serialize proof")?,
+        proving,
+    ))
+}
+
+/// Result of replaying a single transaction of a block.
+pub struct TxReplay {
+    pub block_number: u64,
+    pub index: usize,
+    pub tx_hash: H256,
+    pub receipt: Receipt,
+    pub transitions: Vec<AccountUpdate>,
+    /// State of the updated accounts and storage slots right before the transaction.
+    pub pre_state: HashMap<Address, AccountPreState>,
+    pub gas_limit: u64,
+    pub base_fee_per_gas: Option<u64>,
+    pub effective_gas_price: Option<U256>,
+    /// Time LEVM took to execute the transaction.
+    pub execution_time: Duration,
+}
+
+pub struct AccountPreState {
+    /// `None` if the account didn't exist.
+    pub account_state: Option<AccountState>,
+    pub storage: HashMap<H256, U256>,
+}
+
+/// Transactions of a block to replay.
+#[derive(Clone, Debug)]
+pub enum TxSelection {
+    Hash(H256),
+    /// Indices of the transactions inside the block.
+    Indices(Range<usize>),
+}
+
+impl TxSelection {
+    fn contains(&self, index: usize, tx_hash: H256) -> bool {
+        match self {
+            TxSelection::Hash(hash) => *hash == tx_hash,
+            TxSelection::Indices(range) => range.contains(&index),
+        }
+    }
+}
+
+/// Executes the block up to the last selected transaction and returns the result of each
+/// selected one. The state override is applied right before the first selected transaction
+/// and the transaction overrides to every selected one.
+pub async fn run_txs(
+    cache: Cache,
+    selection: &TxSelection,
+    overrides: &Overrides,
+) -> eyre::Result<Vec<TxReplay>> {
+    run_txs_in_block(cache, 0, selection, overrides).await
+}
+
+/// Replays the selected transactions of the block at `block_index` in the cache, like
+/// `run_txs` does for the first one.
+pub async fn run_txs_in_block(
+    cache: Cache,
+    block_index: usize,
+    selection: &TxSelection,
+    overrides: &Overrides,
+) -> eyre::Result<Vec<TxReplay>> {
+    let (block, mut wrapped_db) = prepare_nth_block_db(cache, block_index)?;
+    check_state_override(&wrapped_db, &overrides.state)?;
+
+    let transactions = block.body.get_transactions_with_sender()?;
+
+    if let TxSelection::Indices(range) = selection
+        && (range.is_empty() || range.end > transactions.len())
+    {
+        return Err(eyre::eyre!(
+            "Invalid transaction range {range:?}, block {} has {} transactions",
+            block.header.number,
+            transactions.len()
+        ));
+    }
+
+    let mut remaining_gas = block.header.gas_limit;
+    let mut replays = Vec::new();
+
+    for (index, (tx, tx_sender)) in transactions.into_iter().enumerate() {
+        let tx_hash = tx.hash();
+        let is_selected = selection.contains(index, tx_hash);
+
+        let (tx, tx_sender) = if is_selected {
+            if replays.is_empty() {
+                apply_state_override(&mut wrapped_db, &overrides.state)?;
+            }
+            overrides.tx.apply(tx, tx_sender)
+        } else {
+            (tx.clone(), tx_sender)
+        };
+
+        let mut vm = new_vm(wrapped_db.clone())?;
+        let mut cumulative_gas_spent = 0;
+        let start = Instant::now();
+        let (receipt, _) = vm.execute_tx(
+            &tx,
+            &block.header,
+            &mut remaining_gas,
+            &mut cumulative_gas_spent,
+            tx_sender,
+        )?;
+        let execution_time = start.elapsed();
+        let account_updates = vm.get_state_transitions()?;
+
+        if !is_selected {
+            wrapped_db.apply_account_updates(&account_updates)?;
+            continue;
+        }
+
+        let pre_state = get_pre_state(&wrapped_db, &account_updates)?;
+        wrapped_db.apply_account_updates(&account_updates)?;
+
+        replays.push(TxReplay {
+            block_number: block.header.number,
+            index,
+            tx_hash,
+            receipt,
+            transitions: account_updates,
+            pre_state,
+            gas_limit: tx.gas_limit(),
+            base_fee_per_gas: block.header.base_fee_per_gas,
+            effective_gas_price: tx.effective_gas_price(block.header.base_fee_per_gas),
+            execution_time,
+        });
+
+        let is_last = match selection {
+            TxSelection::Hash(_) => true,
+            TxSelection::Indices(range) => index + 1 == range.end,
+        };
+        if is_last {
+            return Ok(replays);
+        }
+    }
+
+    Err(eyre::Error::msg("transaction not found inside block"))
+}
+
+/// Reads the values the account updates overwrite, before they are applied.
+fn get_pre_state(
+    db: &GuestProgramStateWrapper,
+    account_updates: &[AccountUpdate],
+) -> eyre::Result<HashMap<Address, AccountPreState>> {
+    account_updates
+        .iter()
+        .map(|update| {
+            let account_state = db.get_account_state(update.address)?;
+            let storage = update
+                .added_storage
+                .keys()
+                .map(|key| -> eyre::Result<_> {
+                    let value = db.get_storage_slot(update.address, *key)?;
+                    Ok((*key, value.unwrap_or_default()))
+                })
+                .collect::<eyre::Result<_>>()?;
+            Ok((
+                update.address,
+                AccountPreState {
+                    account_state,
+                    storage,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Executes the block up to the given transaction and returns the call trace of that
+/// transaction, as built by `debug_traceTransaction`'s `callTracer`.
+pub async fn trace_tx_calls(cache: Cache, tx_hash: H256) -> eyre::Result<CallTrace> {
+    let (block, mut wrapped_db) = prepare_block_db(cache)?;
 
     let mut remaining_gas = block.header.gas_limit;
 
+    for (tx_index, (tx, tx_sender)) in block
+        .body
+        .get_transactions_with_sender()?
+        .into_iter()
+        .enumerate()
+    {
+        let mut vm = new_vm(wrapped_db.clone())?;
+        if tx.hash() == tx_hash {
+            return Ok(vm.trace_tx_calls(&block, tx_index, false, true)?);
+        }
+        let mut cumulative_gas_spent = 0;
+        vm.execute_tx(
+            tx,
+            &block.header,
+            &mut remaining_gas,
+            &mut cumulative_gas_spent,
+            tx_sender,
+        )?;
+        let account_updates = vm.get_state_transitions()?;
+        wrapped_db.apply_account_updates(&account_updates)?;
+    }
+
+    Err(eyre::Error::msg("transaction not found inside block"))
+}
+
+/// Builds the witness database of the cached block and applies the system calls that run
+/// before its first transaction.
+fn prepare_block_db(cache: Cache) -> eyre::Result<(Block, GuestProgramStateWrapper)> {
+    prepare_nth_block_db(cache, 0)
+}
+
+/// Builds the state the transactions of the block at `index` in the cache run on. The
+/// witness holds the state before the first block, so the blocks before it are executed
+/// whole on top of it.
+fn prepare_nth_block_db(
+    cache: Cache,
+    index: usize,
+) -> eyre::Result<(Block, GuestProgramStateWrapper)> {
+    let first_block_number = cache.get_first_block_number()?;
+    let block = cache
+        .blocks
+        .get(index)
+        .ok_or(eyre::Error::msg("missing block data"))?
+        .clone();
+
     let execution_witness = cache.witness;
     let network = cache.network;
     let chain_config = network
@@ -128,7 +639,7 @@ pub async fn run_tx(cache: Cache, tx_hash: H256) -> eyre::Result<(Receipt, Vec<A
     let execution_witness = execution_witness_from_rpc_chain_config(
         execution_witness,
         chain_config,
-        block.header.number,
+        first_block_number,
     )
     .wrap_err("Failed to convert execution witness")?;
 
@@ -137,43 +648,36 @@ pub async fn run_tx(cache: Cache, tx_hash: H256) -> eyre::Result<(Receipt, Vec<A
 
     let mut wrapped_db = GuestProgramStateWrapper::new(guest_program_state);
 
-    #[cfg(feature = "l2")]
-    let fee_config = FeeConfig::default();
+    for previous_block in &cache.blocks[..index] {
+        let mut vm = new_vm(wrapped_db.clone())?;
+        vm.execute_block(previous_block).wrap_err_with(|| {
+            format!("Failed to execute block {}", previous_block.header.number)
+        })?;
+        let account_updates = vm.get_state_transitions()?;
+        wrapped_db.apply_account_updates(&account_updates)?;
+    }
 
     #[cfg(feature = "l2")]
-    let vm_type = VMType::L2(fee_config);
+    let vm_type = VMType::L2(FeeConfig::default());
     #[cfg(not(feature = "l2"))]
     let vm_type = VMType::L1;
 
     let changes = {
         let store: Arc<DynVmDatabase> = Arc::new(Box::new(wrapped_db.clone()));
         let mut db = GeneralizedDatabase::new(store.clone());
-        LEVM::prepare_block(block, &mut db, vm_type)?;
+        LEVM::prepare_block(&block, &mut db, vm_type)?;
         LEVM::get_state_transitions(&mut db)?
     };
     wrapped_db.apply_account_updates(&changes)?;
 
-    for (tx, tx_sender) in block.body.get_transactions_with_sender()? {
-        #[cfg(feature = "l2")]
-        let mut vm = Evm::new_for_l2(wrapped_db.clone(), fee_config)?;
-        #[cfg(not(feature = "l2"))]
-        let mut vm = Evm::new_for_l1(wrapped_db.clone());
-        let mut cumulative_gas_spent = 0;
-        let (receipt, _) = vm.execute_tx(
-            tx,
-            &block.header,
-            &mut remaining_gas,
-            &mut cumulative_gas_spent,
-            tx_sender,
-        )?;
-        let account_updates = vm.get_state_transitions()?;
-        wrapped_db.apply_account_updates(&account_updates)?;
-        if tx.hash() == tx_hash {
-            return Ok((receipt, account_updates));
-        }
-    }
+    Ok((block, wrapped_db))
+}
 
-    Err(eyre::Error::msg("transaction not found inside block"))
+fn new_vm(db: GuestProgramStateWrapper) -> eyre::Result<Evm> {
+    #[cfg(feature = "l2")]
+    return Ok(Evm::new_for_l2(db, FeeConfig::default())?);
+    #[cfg(not(feature = "l2"))]
+    Ok(Evm::new_for_l1(db))
 }
 
 #[cfg(not(feature = "l2"))]
@@ -209,6 +713,13 @@ pub fn get_l1_input(cache: Cache) -> eyre::Result<ProgramInput> {
     })
 }
 
+fn get_input(cache: Cache) -> eyre::Result<ProgramInput> {
+    #[cfg(feature = "l2")]
+    return get_l2_input(cache);
+    #[cfg(not(feature = "l2"))]
+    return get_l1_input(cache);
+}
+
 /// Extract a meaningful error message from panic information.
 fn extract_panic_message(panic_info: &Box<dyn std::any::Any + Send>) -> String {
     if let Some(s) = panic_info.downcast_ref::<String>() {
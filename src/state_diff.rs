@@ -0,0 +1,418 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use ethrex_common::{
+    Address, H256, U256,
+    constants::{EMPTY_KECCACK_HASH, EMPTY_TRIE_HASH},
+    types::{
+        AccountUpdate, ChainConfig, block_execution_witness::GuestProgramState,
+        block_execution_witness::RpcExecutionWitness,
+    },
+};
+use ethrex_rpc::debug::execution_witness::execution_witness_from_rpc_chain_config;
+use ethrex_vm::{Evm, GuestProgramStateWrapper, VmDatabase};
+use eyre::{OptionExt, WrapErr};
+
+#[cfg(feature = "l2")]
+use ethrex_common::types::fee_config::FeeConfig;
+
+use crate::{
+    cache::Cache,
+    rpc::{Account, RpcClient, get_account},
+};
+
+/// Where the canonical post-state of a block is read from.
+pub enum PostState {
+    /// `eth_getProof` at the block itself.
+    Rpc {
+        client: RpcClient,
+        block_number: u64,
+    },
+    /// The witness of the next block, whose initial state is the post-state of the block.
+    /// Only accounts and slots touched by the next block are part of it.
+    Witness(GuestProgramStateWrapper),
+}
+
+/// State of an account in the canonical post-state, or in the one computed by LEVM.
+struct PostAccount {
+    exists: bool,
+    nonce: u64,
+    balance: U256,
+    code_hash: H256,
+    storage: HashMap<H256, U256>,
+}
+
+impl PostState {
+    /// Builds the post-state of a block from the witness of the block after it.
+    pub fn from_next_block_cache(cache: Cache) -> eyre::Result<Self> {
+        let chain_config = cache.get_chain_config()?;
+        let block_number = cache.get_first_block_number()?;
+        Ok(PostState::Witness(witness_db(
+            cache.witness,
+            chain_config,
+            block_number,
+        )?))
+    }
+
+    /// Returns the post-state of `address` and the given storage slots, or `None` if the
+    /// source doesn't cover them.
+    async fn account(&self, address: Address, keys: &[H256]) -> eyre::Result<Option<PostAccount>> {
+        match self {
+            PostState::Rpc {
+                client,
+                block_number,
+            } => {
+                let codes = Arc::new(Mutex::new(HashMap::new()));
+                let account = get_account(client, *block_number as usize, &address, keys, &codes)
+                    .await
+                    .wrap_err_with(|| format!("Failed to fetch post-state of {address:#x}"))?;
+
+                Ok(Some(match account {
+                    Account::Existing {
+                        account_state,
+                        storage,
+                        ..
+                    } => PostAccount {
+                        exists: true,
+                        nonce: account_state.nonce,
+                        balance: account_state.balance,
+                        code_hash: account_state.code_hash,
+                        storage,
+                    },
+                    Account::NonExisting { .. } => PostAccount::non_existing(),
+                }))
+            }
+            PostState::Witness(db) => {
+                // Missing trie nodes mean the next block didn't touch this data. Slots it
+                // doesn't cover are left out of the storage and not compared.
+                let Ok(account_state) = db.get_account_state(address) else {
+                    return Ok(None);
+                };
+                let mut storage = HashMap::new();
+                for key in keys {
+                    if let Ok(value) = db.get_storage_slot(address, *key) {
+                        storage.insert(*key, value.unwrap_or_default());
+                    }
+                }
+
+                Ok(Some(match account_state {
+                    Some(account_state) => PostAccount {
+                        exists: true,
+                        nonce: account_state.nonce,
+                        balance: account_state.balance,
+                        code_hash: account_state.code_hash,
+                        storage,
+                    },
+                    None => PostAccount {
+                        storage,
+                        ..PostAccount::non_existing()
+                    },
+                }))
+            }
+        }
+    }
+}
+
+impl PostAccount {
+    fn non_existing() -> Self {
+        Self {
+            exists: false,
+            nonce: 0,
+            balance: U256::zero(),
+            code_hash: *EMPTY_KECCACK_HASH,
+            storage: HashMap::new(),
+        }
+    }
+}
+
+/// A value that differs between the state computed by LEVM and the canonical post-state.
+pub struct FieldDiff {
+    pub field: StateField,
+    pub computed: String,
+    pub canonical: String,
+}
+
+pub enum StateField {
+    Existence,
+    Nonce,
+    Balance,
+    Code,
+    Storage(H256),
+}
+
+impl Display for StateField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateField::Existence => write!(f, "exists"),
+            StateField::Nonce => write!(f, "nonce"),
+            StateField::Balance => write!(f, "balance"),
+            StateField::Code => write!(f, "code hash"),
+            StateField::Storage(key) => write!(f, "storage[{key:#x}]"),
+        }
+    }
+}
+
+/// Per-account differences between the post-state computed by LEVM and the canonical one.
+#[derive(Default)]
+pub struct StateDiff {
+    pub accounts: BTreeMap<Address, Vec<FieldDiff>>,
+    /// Compared accounts that the post-state source doesn't cover.
+    pub unverified: Vec<Address>,
+    /// Number of accounts compared: the ones updated by LEVM and the ones in the witness,
+    /// which the canonical execution touched.
+    pub compared_accounts: usize,
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "State Diff: {} of {} compared accounts differ from the canonical post-state",
+            self.accounts.len(),
+            self.compared_accounts
+        )?;
+        for (address, fields) in &self.accounts {
+            writeln!(f, "  {address:#x}")?;
+            for diff in fields {
+                writeln!(
+                    f,
+                    "    {}: computed {}, canonical {}",
+                    diff.field, diff.computed, diff.canonical
+                )?;
+            }
+        }
+        if !self.unverified.is_empty() {
+            writeln!(
+                f,
+                "  Not covered by the post-state: {}",
+                self.unverified
+                    .iter()
+                    .map(|address| format!("{address:#x}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes the block of the cache with LEVM on top of its witness and compares the
+/// resulting post-state with the canonical one.
+///
+/// Both sides' changes are covered: every account LEVM updated, and every account in the
+/// witness, which holds whatever the canonical execution touched. For each account, the
+/// storage slots LEVM wrote and the ones of the witness the account had before the block are
+/// compared. Slots the canonical execution created but LEVM never wrote can't be attributed
+/// to an account, so they aren't.
+pub async fn diff_post_state(cache: Cache, post_state: &PostState) -> eyre::Result<StateDiff> {
+    let chain_config = cache.get_chain_config()?;
+    let block_number = cache.get_first_block_number()?;
+
+    let (witness_addresses, witness_slots) = witness_keys(&cache.witness.keys);
+    let pre_state = witness_db(cache.witness.clone(), chain_config, block_number)?;
+
+    let mut updates: HashMap<Address, AccountUpdate> = execute_block(cache)?
+        .into_iter()
+        .map(|update| (update.address, update))
+        .collect();
+    let addresses: BTreeSet<Address> = updates.keys().copied().chain(witness_addresses).collect();
+
+    let mut state_diff = StateDiff {
+        compared_accounts: addresses.len(),
+        ..Default::default()
+    };
+
+    for address in addresses {
+        let update = updates.remove(&address);
+        let Some(computed) = computed_account(&pre_state, address, update, &witness_slots) else {
+            state_diff.unverified.push(address);
+            continue;
+        };
+
+        let keys: Vec<H256> = computed.storage.keys().copied().collect();
+        let Some(canonical) = post_state.account(address, &keys).await? else {
+            state_diff.unverified.push(address);
+            continue;
+        };
+
+        let fields = diff_account(&computed, &canonical);
+        if !fields.is_empty() {
+            state_diff.accounts.insert(address, fields);
+        }
+    }
+
+    Ok(state_diff)
+}
+
+/// Splits the keys of a witness into the addresses and the storage slots it covers.
+fn witness_keys(keys: &[bytes::Bytes]) -> (Vec<Address>, Vec<H256>) {
+    let addresses = keys
+        .iter()
+        .filter(|key| key.len() == Address::len_bytes())
+        .map(|key| Address::from_slice(key))
+        .collect();
+    let slots = keys
+        .iter()
+        .filter(|key| key.len() == H256::len_bytes())
+        .map(|key| H256::from_slice(key))
+        .collect();
+    (addresses, slots)
+}
+
+/// Post-state of an account as computed by LEVM: its pre-state from the witness with the
+/// update applied on top. Returns `None` if the witness doesn't cover the account.
+fn computed_account(
+    pre_state: &GuestProgramStateWrapper,
+    address: Address,
+    update: Option<AccountUpdate>,
+    witness_slots: &[H256],
+) -> Option<PostAccount> {
+    let pre_account = pre_state.get_account_state(address).ok()?;
+
+    // Slots of the witness this account had before the block.
+    let pre_storage: HashMap<H256, U256> = pre_account
+        .as_ref()
+        .filter(|account| account.storage_root != *EMPTY_TRIE_HASH)
+        .map(|_| {
+            witness_slots
+                .iter()
+                .filter_map(|key| match pre_state.get_storage_slot(address, *key) {
+                    Ok(Some(value)) => Some((*key, value)),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut account = match pre_account {
+        Some(account_state) => PostAccount {
+            exists: true,
+            nonce: account_state.nonce,
+            balance: account_state.balance,
+            code_hash: account_state.code_hash,
+            storage: pre_storage,
+        },
+        None => PostAccount::non_existing(),
+    };
+
+    let Some(update) = update else {
+        return Some(account);
+    };
+
+    if update.removed || update.removed_storage {
+        account
+            .storage
+            .values_mut()
+            .for_each(|value| *value = U256::zero());
+    }
+    account.storage.extend(update.added_storage);
+
+    if update.removed {
+        account = PostAccount {
+            storage: account.storage,
+            ..PostAccount::non_existing()
+        };
+    } else if let Some(info) = update.info {
+        account.nonce = info.nonce;
+        account.balance = info.balance;
+        account.code_hash = info.code_hash;
+        // Empty accounts don't exist as far as the state trie is concerned (EIP-161).
+        account.exists = !info.is_empty();
+    }
+
+    Some(account)
+}
+
+fn diff_account(computed: &PostAccount, canonical: &PostAccount) -> Vec<FieldDiff> {
+    let mut fields = Vec::new();
+
+    if computed.exists != canonical.exists {
+        fields.push(FieldDiff {
+            field: StateField::Existence,
+            computed: computed.exists.to_string(),
+            canonical: canonical.exists.to_string(),
+        });
+    }
+
+    if computed.exists {
+        if computed.nonce != canonical.nonce {
+            fields.push(FieldDiff {
+                field: StateField::Nonce,
+                computed: computed.nonce.to_string(),
+                canonical: canonical.nonce.to_string(),
+            });
+        }
+        if computed.balance != canonical.balance {
+            fields.push(FieldDiff {
+                field: StateField::Balance,
+                computed: computed.balance.to_string(),
+                canonical: canonical.balance.to_string(),
+            });
+        }
+        if computed.code_hash != canonical.code_hash {
+            fields.push(FieldDiff {
+                field: StateField::Code,
+                computed: format!("{:#x}", computed.code_hash),
+                canonical: format!("{:#x}", canonical.code_hash),
+            });
+        }
+    }
+
+    let mut storage: Vec<_> = computed.storage.iter().collect();
+    storage.sort_by_key(|(key, _)| **key);
+    for (key, value) in storage {
+        let Some(canonical_value) = canonical.storage.get(key) else {
+            continue;
+        };
+        if value != canonical_value {
+            fields.push(FieldDiff {
+                field: StateField::Storage(*key),
+                computed: value.to_string(),
+                canonical: canonical_value.to_string(),
+            });
+        }
+    }
+
+    fields
+}
+
+/// Executes the block with LEVM the way `run_txs` does and returns the resulting account updates.
+fn execute_block(cache: Cache) -> eyre::Result<Vec<AccountUpdate>> {
+    let chain_config = cache.get_chain_config()?;
+    let block = cache
+        .blocks
+        .first()
+        .ok_or_eyre("missing block data")?
+        .clone();
+
+    let wrapped_db = witness_db(cache.witness, chain_config, block.header.number)?;
+
+    #[cfg(feature = "l2")]
+    let mut vm = Evm::new_for_l2(wrapped_db, FeeConfig::default())?;
+    #[cfg(not(feature = "l2"))]
+    let mut vm = Evm::new_for_l1(wrapped_db);
+
+    vm.execute_block(&block)
+        .wrap_err("LEVM failed to execute the block")?;
+
+    Ok(vm.get_state_transitions()?)
+}
+
+/// Builds the database LEVM executes a block on from the block's witness.
+pub fn witness_db(
+    witness: RpcExecutionWitness,
+    chain_config: ChainConfig,
+    block_number: u64,
+) -> eyre::Result<GuestProgramStateWrapper> {
+    let execution_witness =
+        execution_witness_from_rpc_chain_config(witness, chain_config, block_number)
+            .wrap_err("Failed to convert execution witness")?;
+
+    let guest_program_state: GuestProgramState =
+        execution_witness.try_into().map_err(eyre::Error::msg)?;
+
+    Ok(GuestProgramStateWrapper::new(guest_program_state))
+}
@@ -0,0 +1,222 @@
+use std::{ffi::OsString, fs::File, io::BufReader, path::PathBuf, time::Duration};
+#[cfg(not(feature = "l2"))]
+use std::{io::BufWriter, path::Path};
+
+use eyre::{OptionExt, WrapErr};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    cache::Cache,
+    cli::{Action, CommonOptions},
+    resource_usage::PhaseUsage,
+    run::CycleCount,
+    timeout::{Phase, Timeout},
+};
+
+/// Flags the supervisor adds to its own command line to run a block in a worker process.
+pub const WORKER_CACHE_FLAG: &str = "--worker-cache";
+pub const WORKER_RESULT_FLAG: &str = "--worker-result";
+/// Flag that enables supervisor mode, removed from the command line of the workers.
+pub const ISOLATE_FLAG: &str = "--isolate";
+/// Time a worker gets on top of its execution and proving timeouts to start, load the cache,
+/// build the program input and write its result.
+const WORKER_GRACE_PERIOD: Duration = Duration::from_secs(120);
+
+/// Results of executing and proving a block, wherever that happened.
+pub struct BlockOutcome {
+    pub execution_result: Option<eyre::Result<Duration>>,
+    pub proving_result: Option<eyre::Result<Duration>>,
+    pub cycles: Option<CycleCount>,
+    /// Guest program output committed by the backend, encoded by `ProgramOutput::encode`.
+    pub public_values: Option<Vec<u8>>,
+    pub usage: PhaseUsage,
+}
+
+/// [`BlockOutcome`] as written by a worker process for its supervisor.
+#[derive(Serialize, Deserialize)]
+struct WorkerResult {
+    execution_result: Option<Result<Duration, WorkerError>>,
+    proving_result: Option<Result<Duration, WorkerError>>,
+    cycles: Option<CycleCount>,
+    public_values: Option<Vec<u8>>,
+    usage: PhaseUsage,
+}
+
+#[derive(Serialize, Deserialize)]
+enum WorkerError {
+    Timeout(Timeout),
+    Failed(String),
+}
+
+impl From<eyre::Report> for WorkerError {
+    fn from(err: eyre::Report) -> Self {
+        match err.downcast::<Timeout>() {
+            Ok(timeout) => WorkerError::Timeout(timeout),
+            Err(err) => WorkerError::Failed(err.to_string()),
+        }
+    }
+}
+
+impl From<WorkerError> for eyre::Report {
+    fn from(err: WorkerError) -> Self {
+        match err {
+            WorkerError::Timeout(timeout) => eyre::Report::new(timeout),
+            WorkerError::Failed(message) => eyre::Error::msg(message),
+        }
+    }
+}
+
+impl From<BlockOutcome> for WorkerResult {
+    fn from(outcome: BlockOutcome) -> Self {
+        Self {
+            execution_result: outcome
+                .execution_result
+                .map(|result| result.map_err(Into::into)),
+            proving_result: outcome
+                .proving_result
+                .map(|result| result.map_err(Into::into)),
+            cycles: outcome.cycles,
+            public_values: outcome.public_values,
+            usage: outcome.usage,
+        }
+    }
+}
+
+impl From<WorkerResult> for BlockOutcome {
+    fn from(result: WorkerResult) -> Self {
+        Self {
+            execution_result: result
+                .execution_result
+                .map(|result| result.map_err(Into::into)),
+            proving_result: result
+                .proving_result
+                .map(|result| result.map_err(Into::into)),
+            cycles: result.cycles,
+            public_values: result.public_values,
+            usage: result.usage,
+        }
+    }
+}
+
+/// Writes the outcome of the block run by this worker for the supervisor to collect.
+#[cfg(not(feature = "l2"))]
+pub fn write_worker_result(path: &Path, outcome: BlockOutcome) -> eyre::Result<()> {
+    let file =
+        File::create(path).wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+    serde_json::to_writer(BufWriter::new(file), &WorkerResult::from(outcome))?;
+    Ok(())
+}
+
+/// Loads the cache a worker was given by its supervisor.
+#[cfg(not(feature = "l2"))]
+pub fn load_worker_cache(path: &Path) -> eyre::Result<Cache> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .ok_or_eyre("invalid worker cache path")?;
+    Cache::load(dir, file_name)
+}
+
+/// Executes or proves the block of the cache in a child process, running this same binary
+/// with the same options. Aborts, OOM kills and segfaults in the child become failures of the
+/// block instead of taking the supervisor down with them.
+pub async fn run_in_worker(cache: &Cache, opts: &CommonOptions) -> BlockOutcome {
+    match spawn_worker(cache, opts).await {
+        Ok(outcome) => outcome,
+        Err(err) if opts.action == Action::Execute => BlockOutcome {
+            execution_result: Some(Err(err)),
+            proving_result: None,
+            cycles: None,
+            public_values: None,
+            usage: PhaseUsage::default(),
+        },
+        Err(err) => BlockOutcome {
+            execution_result: None,
+            proving_result: Some(Err(err)),
+            cycles: None,
+            public_values: None,
+            usage: PhaseUsage::default(),
+        },
+    }
+}
+
+async fn spawn_worker(cache: &Cache, opts: &CommonOptions) -> eyre::Result<BlockOutcome> {
+    let block_number = cache.get_first_block_number()?;
+    let cache_path = cache.path();
+    let result_path = worker_result_path(cache)?;
+
+    // A result left behind by an earlier worker must not be mistaken for this one's.
+    let _ = std::fs::remove_file(&result_path);
+
+    let args: Vec<OsString> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| !is_isolate_flag(arg))
+        .collect();
+
+    info!("Spawning worker process for block {block_number}");
+
+    // Killed when dropped, so a worker that outlives its time limit doesn't keep running.
+    let mut worker = tokio::process::Command::new(std::env::current_exe()?)
+        .args(args)
+        .arg(WORKER_CACHE_FLAG)
+        .arg(&cache_path)
+        .arg(WORKER_RESULT_FLAG)
+        .arg(&result_path)
+        .kill_on_drop(true)
+        .spawn()
+        .wrap_err("Failed to spawn worker process")?;
+
+    let status = match worker_time_limit(opts) {
+        Some((phase, after)) => tokio::time::timeout(after, worker.wait())
+            .await
+            .map_err(|_| eyre::Report::new(Timeout { phase, after }))?,
+        None => worker.wait().await,
+    }
+    .wrap_err("Failed to wait for worker process")?;
+
+    if !status.success() {
+        return Err(eyre::eyre!(
+            "Worker process for block {block_number} crashed ({status})"
+        ));
+    }
+
+    let file = File::open(&result_path).wrap_err_with(|| {
+        format!("Worker process for block {block_number} exited without a result")
+    })?;
+    let result: WorkerResult = serde_json::from_reader(BufReader::new(file))?;
+    let _ = std::fs::remove_file(&result_path);
+
+    Ok(result.into())
+}
+
+/// Whether the argument enables supervisor mode, either as `--isolate` or `--isolate=<value>`.
+fn is_isolate_flag(arg: &OsString) -> bool {
+    let arg = arg.to_string_lossy();
+    arg == ISOLATE_FLAG || arg.starts_with(&format!("{ISOLATE_FLAG}="))
+}
+
+/// Time a worker gets before it's killed: the `--execute-timeout` and `--prove-timeout` of the
+/// phases it runs, plus [`WORKER_GRACE_PERIOD`]. `None` if any of those phases has no timeout.
+fn worker_time_limit(opts: &CommonOptions) -> Option<(Phase, Duration)> {
+    let (phase, limit) = match opts.action {
+        Action::Execute => (Phase::Execution, opts.execute_timeout?),
+        // ZisK doesn't execute before proving, see `exec_and_prove`.
+        #[cfg(feature = "zisk")]
+        Action::Prove => (Phase::Proving, opts.prove_timeout?),
+        #[cfg(not(feature = "zisk"))]
+        Action::Prove => (Phase::Proving, opts.execute_timeout? + opts.prove_timeout?),
+    };
+    Some((phase, Duration::from_secs(limit) + WORKER_GRACE_PERIOD))
+}
+
+fn worker_result_path(cache: &Cache) -> eyre::Result<PathBuf> {
+    let file_name = cache
+        .path()
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .map(|file_stem| format!("{file_stem}_worker_result.json"))
+        .ok_or_eyre("invalid cache path")?;
+    Ok(cache.dir.join(file_name))
+}
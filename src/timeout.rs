@@ -0,0 +1,78 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// Phase of a replay that can be given a time limit.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Phase {
+    Fetch,
+    Execution,
+    Proving,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Fetch => write!(f, "Fetching block data"),
+            Phase::Execution => write!(f, "Execution"),
+            Phase::Proving => write!(f, "Proving"),
+        }
+    }
+}
+
+/// Error returned when a phase takes longer than its time limit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Timeout {
+    pub phase: Phase,
+    pub after: Duration,
+}
+
+impl Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} timed out after {:?}", self.phase, self.after)
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+pub fn is_timeout(err: &eyre::Report) -> bool {
+    err.downcast_ref::<Timeout>().is_some()
+}
+
+/// Awaits `future`, failing with [`Timeout`] if it takes longer than `timeout`. The future is
+/// dropped when the timeout expires.
+pub async fn with_timeout<T>(
+    phase: Phase,
+    timeout: Option<Duration>,
+    future: impl Future<Output = eyre::Result<T>>,
+) -> eyre::Result<T> {
+    let Some(after) = timeout else {
+        return future.await;
+    };
+
+    tokio::time::timeout(after, future)
+        .await
+        .map_err(|_| eyre::Report::new(Timeout { phase, after }))?
+}
+
+/// Like [`with_timeout`], for futures that block their thread, like zkVM execution and
+/// proving. They run on a blocking thread so they can be abandoned when the timeout expires.
+/// That thread can't be interrupted, so it keeps running in the background until it finishes
+/// or the process exits, which is why `--isolate` workers exit as soon as they're done.
+pub async fn with_blocking_timeout<T: Send + 'static>(
+    phase: Phase,
+    timeout: Option<Duration>,
+    future: impl Future<Output = T> + Send + 'static,
+) -> eyre::Result<T> {
+    let handle = tokio::runtime::Handle::current();
+    let task = tokio::task::spawn_blocking(move || handle.block_on(future));
+
+    let result = match timeout {
+        Some(after) => tokio::time::timeout(after, task)
+            .await
+            .map_err(|_| eyre::Report::new(Timeout { phase, after }))?,
+        None => task.await,
+    };
+
+    result.map_err(|e| eyre::eyre!("{phase} task failed: {e}"))
+}